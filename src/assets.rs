@@ -0,0 +1,309 @@
+use crate::animation::Animation;
+use crate::audio::{Audio, SoundID};
+use crate::error::Result;
+use crate::sheet::SpriteSheet;
+use crate::text::Font;
+use crate::texture::Texture;
+use crate::tiles::{Tile, Tileset};
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+// Which asset, out of everything of type T in an Assets.
+// Only good for the Assets that gave it out.
+pub struct Handle<T> {
+    index: usize,
+    marker: PhantomData<fn() -> T>,
+}
+
+// Written out by hand since deriving would want T to be Copy, Eq, etc. too
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<T> Copy for Handle<T> {}
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+impl<T> Eq for Handle<T> {}
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+    }
+}
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Handle({})", self.index)
+    }
+}
+
+// Everything of one type, and the names to find it by
+pub struct Store<T> {
+    items: Vec<Rc<T>>,
+    names: HashMap<String, usize>,
+}
+
+impl<T> Default for Store<T> {
+    fn default() -> Self {
+        Self {
+            items: vec![],
+            names: HashMap::new(),
+        }
+    }
+}
+
+impl<T> Store<T> {
+    fn find(&self, name: &str) -> Option<Handle<T>> {
+        self.names.get(name).map(|&index| Handle {
+            index,
+            marker: PhantomData,
+        })
+    }
+    fn insert(&mut self, name: &str, item: T) -> Handle<T> {
+        self.items.push(Rc::new(item));
+        let index = self.items.len() - 1;
+        self.names.insert(name.to_string(), index);
+        Handle {
+            index,
+            marker: PhantomData,
+        }
+    }
+}
+
+// The kinds of thing Assets keeps track of
+pub trait Asset: Sized {
+    fn store(assets: &Assets) -> &Store<Self>;
+    fn store_mut(assets: &mut Assets) -> &mut Store<Self>;
+}
+
+impl Asset for Texture {
+    fn store(assets: &Assets) -> &Store<Self> {
+        &assets.textures
+    }
+    fn store_mut(assets: &mut Assets) -> &mut Store<Self> {
+        &mut assets.textures
+    }
+}
+impl Asset for Font {
+    fn store(assets: &Assets) -> &Store<Self> {
+        &assets.fonts
+    }
+    fn store_mut(assets: &mut Assets) -> &mut Store<Self> {
+        &mut assets.fonts
+    }
+}
+impl Asset for Tileset {
+    fn store(assets: &Assets) -> &Store<Self> {
+        &assets.tilesets
+    }
+    fn store_mut(assets: &mut Assets) -> &mut Store<Self> {
+        &mut assets.tilesets
+    }
+}
+impl Asset for Animation {
+    fn store(assets: &Assets) -> &Store<Self> {
+        &assets.animations
+    }
+    fn store_mut(assets: &mut Assets) -> &mut Store<Self> {
+        &mut assets.animations
+    }
+}
+
+// What a sprite sheet added, so a reload can put its animations back in
+// the same places
+struct LoadedSheet {
+    texture: Handle<Texture>,
+    animations: HashMap<String, Handle<Animation>>,
+}
+
+// Loads a game's assets from one folder, once each.  Things loaded from a
+// file are named by their path (relative to the folder), so loading the same
+// path again just hands back the same handle; anything can also be given
+// extra names with `alias`.
+pub struct Assets {
+    root: PathBuf,
+    textures: Store<Texture>,
+    fonts: Store<Font>,
+    tilesets: Store<Tileset>,
+    animations: Store<Animation>,
+    sounds: HashMap<String, SoundID>,
+    sheets: HashMap<String, LoadedSheet>,
+    missing: Vec<PathBuf>,
+}
+
+impl Assets {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            textures: Store::default(),
+            fonts: Store::default(),
+            tilesets: Store::default(),
+            animations: Store::default(),
+            sounds: HashMap::new(),
+            sheets: HashMap::new(),
+            missing: vec![],
+        }
+    }
+
+    // Where a path given to the loaders really is
+    pub fn resolve(&self, path: &str) -> PathBuf {
+        self.root.join(path)
+    }
+
+    pub fn texture(&mut self, path: &str) -> Result<Handle<Texture>> {
+        if let Some(h) = self.textures.find(path) {
+            return Ok(h);
+        }
+        let full = self.resolve(path);
+        let texture = self.loaded(&full, Texture::with_file(&full))?;
+        Ok(self.textures.insert(path, texture))
+    }
+
+    // A font's image shares its texture with `texture(path)`
+    pub fn font(&mut self, path: &str) -> Result<Handle<Font>> {
+        if let Some(h) = self.fonts.find(path) {
+            return Ok(h);
+        }
+        let image = self.texture(path)?;
+        let font = Font {
+            image: Rc::clone(self.get(image)),
+        };
+        Ok(self.fonts.insert(path, font))
+    }
+
+    // Tilesets are named rather than found by path, since the same image
+    // could be cut up with different tile properties
+    pub fn tileset(&mut self, name: &str, path: &str, tiles: Vec<Tile>) -> Result<Handle<Tileset>> {
+        if let Some(h) = self.tilesets.find(name) {
+            return Ok(h);
+        }
+        let image = self.texture(path)?;
+        let tileset = Tileset::new(tiles, self.get(image))?;
+        Ok(self.tilesets.insert(name, tileset))
+    }
+
+    // Loads an Aseprite or TexturePacker sprite sheet's JSON and its image.
+    // Each of its animations can then be found by name; a later sheet with
+    // an animation of the same name takes the name over.
+    pub fn sheet(&mut self, path: &str) -> Result<Handle<Texture>> {
+        if let Some(sheet) = self.sheets.get(path) {
+            return Ok(sheet.texture);
+        }
+        let full = self.resolve(path);
+        let sheet = self.loaded(&full, SpriteSheet::load(&full))?;
+        let image = match sheet.image.strip_prefix(&self.root) {
+            Ok(image) => image.to_string_lossy().into_owned(),
+            Err(_) => sheet.image.to_string_lossy().into_owned(),
+        };
+        let texture = self.texture(&image)?;
+        let animations = sheet
+            .animations
+            .into_iter()
+            .map(|(name, animation)| {
+                let h = self.add(&name, animation);
+                (name, h)
+            })
+            .collect();
+        self.sheets.insert(
+            path.to_string(),
+            LoadedSheet {
+                texture,
+                animations,
+            },
+        );
+        Ok(texture)
+    }
+
+    // For things that don't come from a file of their own, like animations
+    // or generated textures.  Replaces whatever was called `name` before.
+    pub fn add<T: Asset>(&mut self, name: &str, asset: T) -> Handle<T> {
+        T::store_mut(self).insert(name, asset)
+    }
+
+    // Loads a sound into audio.  A sound that won't load gets a silent
+    // stand-in (and goes on the missing list), since a game can carry on
+    // without it.
+    pub fn sound(&mut self, audio: &mut Audio, path: &str) -> SoundID {
+        if let Some(&id) = self.sounds.get(path) {
+            return id;
+        }
+        let full = self.resolve(path);
+        let id = match audio.load(&full.to_string_lossy()) {
+            Ok(id) => id,
+            Err(_) => {
+                self.missing.push(full);
+                audio.add_silent()
+            }
+        };
+        self.sounds.insert(path.to_string(), id);
+        id
+    }
+
+    // Another name for something already loaded
+    pub fn alias<T: Asset>(&mut self, name: &str, handle: Handle<T>) {
+        T::store_mut(self)
+            .names
+            .insert(name.to_string(), handle.index);
+    }
+
+    pub fn find<T: Asset>(&self, name: &str) -> Option<Handle<T>> {
+        T::store(self).find(name)
+    }
+
+    pub fn get<T: Asset>(&self, handle: Handle<T>) -> &Rc<T> {
+        &T::store(self).items[handle.index]
+    }
+
+    // For a file that's changed on disk: loads it again into the texture
+    // or sprite sheet that came from it.  A texture changes in place,
+    // wherever it's been shared.  A sheet's animations are new values under
+    // the same handles, so anything holding on to one of the old ones should
+    // `get` it again.  Ok(false) means it isn't one of ours.
+    pub fn reload(&mut self, path: &Path) -> Result<bool> {
+        let index = self
+            .textures
+            .names
+            .iter()
+            .find(|(name, _)| self.resolve(name) == path)
+            .map(|(_, &index)| index);
+        if let Some(index) = index {
+            self.textures.items[index].reload(path)?;
+            return Ok(true);
+        }
+        let sheet = match self.sheets.keys().find(|name| self.resolve(name) == path) {
+            Some(name) => name.clone(),
+            None => return Ok(false),
+        };
+        let reloaded = SpriteSheet::load(path)?;
+        let loaded = self.sheets.get_mut(&sheet).expect("Sheet was just found");
+        for (name, animation) in reloaded.animations {
+            match loaded.animations.get(&name) {
+                Some(h) => self.animations.items[h.index] = Rc::new(animation),
+                None => {
+                    let h = self.animations.insert(&name, animation);
+                    loaded.animations.insert(name, h);
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    // Everything that's failed to load so far, with the root folder included
+    pub fn missing(&self) -> &[PathBuf] {
+        &self.missing
+    }
+
+    // Notes down failures on the way through
+    fn loaded<T>(&mut self, path: &Path, result: Result<T>) -> Result<T> {
+        if result.is_err() {
+            self.missing.push(path.to_path_buf());
+        }
+        result
+    }
+}
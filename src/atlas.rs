@@ -0,0 +1,269 @@
+use crate::error::{Error, Result};
+use crate::texture::Texture;
+use crate::types::{Rect, Rectf};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+// Lots of little images packed into one texture, each found by name.
+// The rects work as bitblt sources and, through `frame`, as animation frames.
+pub struct Atlas {
+    pub texture: Rc<Texture>,
+    pub regions: BTreeMap<String, Rect>,
+}
+
+// What gets saved next to the atlas image
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    // Relative to the manifest
+    image: String,
+    width: usize,
+    height: usize,
+    regions: BTreeMap<String, Region>,
+}
+
+// Laid out like the frame rects in Aseprite and TexturePacker JSON too
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Region {
+    pub(crate) x: i32,
+    pub(crate) y: i32,
+    pub(crate) w: u16,
+    pub(crate) h: u16,
+}
+
+impl Atlas {
+    // Packs the images into a texture `width` wide and as short as they'll
+    // go.  `padding` clear pixels go between images, so a scaled or rotated
+    // draw doesn't pick up the edge of its neighbour.
+    pub fn pack(images: Vec<(String, Texture)>, width: usize, padding: usize) -> Result<Self> {
+        // Tallest first leaves the fewest gaps under the skyline
+        let mut order: Vec<usize> = (0..images.len()).collect();
+        order.sort_by_key(|&i| (Reverse(images[i].1.height), Reverse(images[i].1.width)));
+        // The padding is allowed to hang off the right edge
+        let mut skyline = Skyline::new(width + padding);
+        let mut regions = BTreeMap::new();
+        let mut height = 0;
+        for &i in order.iter() {
+            let (name, image) = &images[i];
+            let (x, y) = skyline
+                .place(image.width + padding, image.height + padding)
+                .ok_or_else(|| {
+                    Error::Atlas(format!(
+                        "{} is {} pixels wide, more than the atlas's {}",
+                        name, image.width, width
+                    ))
+                })?;
+            height = height.max(y + image.height);
+            let rect = Rect {
+                x: x as i32,
+                y: y as i32,
+                w: image.width as u16,
+                h: image.height as u16,
+            };
+            if regions.insert(name.clone(), rect).is_some() {
+                return Err(Error::Atlas(format!("two images are called {}", name)));
+            }
+        }
+
+        let mut texture = Texture::blank(width, height);
+        let pitch = texture.pitch();
+        let buffer = texture.buffer_mut();
+        for (name, image) in images.iter() {
+            let r = regions[name];
+            let src = image.buffer();
+            let row = image.pitch();
+            for y in 0..image.height {
+                let to = (r.y as usize + y) * pitch + r.x as usize * 4;
+                buffer[to..(to + row)].copy_from_slice(&src[(y * row)..((y + 1) * row)]);
+            }
+        }
+        Ok(Self {
+            texture: Rc::new(texture),
+            regions,
+        })
+    }
+
+    // Each image is named after its file, without the extension
+    pub fn pack_files(paths: &[&Path], width: usize, padding: usize) -> Result<Self> {
+        let mut images = vec![];
+        for path in paths.iter() {
+            let name = path
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            images.push((name, Texture::with_file(path)?));
+        }
+        Self::pack(images, width, padding)
+    }
+
+    pub fn region(&self, name: &str) -> Option<Rect> {
+        self.regions.get(name).copied()
+    }
+
+    // The same rect, the way Animation wants it
+    pub fn frame(&self, name: &str) -> Option<Rectf> {
+        self.region(name).map(|r| Rectf {
+            x: r.x as f32,
+            y: r.y as f32,
+            w: r.w,
+            h: r.h,
+        })
+    }
+
+    // Animation frames in the order given, or None if any are missing
+    pub fn frames(&self, names: &[&str]) -> Option<Vec<Rectf>> {
+        names.iter().map(|name| self.frame(name)).collect()
+    }
+
+    // Writes the image to path and the manifest beside it, with a .json
+    // extension instead
+    pub fn save(&self, path: &Path) -> Result<()> {
+        self.texture.save_png(path)?;
+        let manifest = Manifest {
+            image: path
+                .file_name()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            width: self.texture.width,
+            height: self.texture.height,
+            regions: self
+                .regions
+                .iter()
+                .map(|(name, r)| {
+                    let region = Region {
+                        x: r.x,
+                        y: r.y,
+                        w: r.w,
+                        h: r.h,
+                    };
+                    (name.clone(), region)
+                })
+                .collect(),
+        };
+        let path = path.with_extension("json");
+        let json = serde_json::to_string_pretty(&manifest).map_err(|source| Error::Json {
+            path: path.clone(),
+            source,
+        })?;
+        let io = |source| Error::Io {
+            path: path.clone(),
+            source,
+        };
+        let mut f = File::create(&path).map_err(io)?;
+        f.write_all(json.as_bytes()).map_err(io)
+    }
+
+    // Takes the path of the manifest, not the image
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path).map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let manifest: Manifest =
+            serde_json::from_reader(BufReader::new(file)).map_err(|source| Error::Json {
+                path: path.to_path_buf(),
+                source,
+            })?;
+        let image = path.with_file_name(&manifest.image);
+        let texture = Texture::with_file(&image)?;
+        if texture.size() != (manifest.width, manifest.height) {
+            return Err(Error::Atlas(format!(
+                "{} is {}x{}, but {} says {}x{}",
+                image.display(),
+                texture.width,
+                texture.height,
+                path.display(),
+                manifest.width,
+                manifest.height
+            )));
+        }
+        let mut regions = BTreeMap::new();
+        for (name, r) in manifest.regions {
+            let rect = Rect {
+                x: r.x,
+                y: r.y,
+                w: r.w,
+                h: r.h,
+            };
+            if !texture.valid_frame(rect) {
+                return Err(Error::Atlas(format!(
+                    "{} goes outside {}",
+                    name,
+                    image.display()
+                )));
+            }
+            regions.insert(name, rect);
+        }
+        Ok(Self {
+            texture: Rc::new(texture),
+            regions,
+        })
+    }
+}
+
+// The top edge of everything packed so far, as (x, y, width) runs from
+// left to right.  New boxes go on top of it, as low down as they fit.
+struct Skyline {
+    width: usize,
+    runs: Vec<(usize, usize, usize)>,
+}
+
+impl Skyline {
+    fn new(width: usize) -> Self {
+        Self {
+            width,
+            runs: vec![(0, 0, width)],
+        }
+    }
+
+    // Where a w by h box went, lowest and then leftmost; None if it's too wide
+    fn place(&mut self, w: usize, h: usize) -> Option<(usize, usize)> {
+        let mut best: Option<(usize, usize)> = None;
+        for (i, &(x, _, _)) in self.runs.iter().enumerate() {
+            if x + w > self.width {
+                break;
+            }
+            // It rests on the highest run underneath it
+            let y = self.runs[i..]
+                .iter()
+                .take_while(|&&(rx, _, _)| rx < x + w)
+                .map(|&(_, ry, _)| ry)
+                .max()
+                .unwrap_or(0);
+            if best.is_none_or(|(bx, by)| (y, x) < (by, bx)) {
+                best = Some((x, y));
+            }
+        }
+        let (x, y) = best?;
+        self.raise(x, w, y + h);
+        Some((x, y))
+    }
+
+    // Whatever was under the box is out of reach now, so the runs it covers
+    // become one run along its top
+    fn raise(&mut self, x: usize, w: usize, top: usize) {
+        let end = x + w;
+        let mut runs = vec![(x, top, w)];
+        for &(rx, ry, rw) in self.runs.iter() {
+            if rx < x {
+                runs.push((rx, ry, (rx + rw).min(x) - rx));
+            }
+            if rx + rw > end {
+                let from = rx.max(end);
+                runs.push((from, ry, rx + rw - from));
+            }
+        }
+        runs.sort_by_key(|&(rx, _, _)| rx);
+        self.runs.clear();
+        for run in runs {
+            match self.runs.last_mut() {
+                Some(last) if last.1 == run.1 => last.2 += run.2,
+                _ => self.runs.push(run),
+            }
+        }
+    }
+}
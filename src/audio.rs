@@ -7,6 +7,9 @@ use kira::instance::StopInstanceSettings;
 use kira::manager::AudioManager;
 use kira::parameter::tween::Tween;
 use kira::sound::handle::SoundHandle;
+use kira::sound::SoundSettings;
+
+use crate::error::{Error, Result};
 
 #[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
 pub struct SoundID(pub usize);
@@ -22,7 +25,8 @@ pub enum AlreadyPlayingAction {
 
 pub struct Audio {
     pub manager: AudioManager,
-    pub sound_handles: Vec<SoundHandle>,
+    // None for a sound that couldn't load; playing it does nothing
+    pub sound_handles: Vec<Option<SoundHandle>>,
     pub instance_handles: Vec<Vec<InstanceHandle>>,
 }
 
@@ -35,11 +39,33 @@ impl Audio {
         }
         Self {
             manager,
-            sound_handles,
+            sound_handles: sound_handles.into_iter().map(Some).collect(),
             instance_handles,
         }
     }
 
+    // Loads a sound with the next SoundID
+    pub fn load(&mut self, path: &str) -> Result<SoundID> {
+        let handle = self
+            .manager
+            .load_sound(path, SoundSettings::default())
+            .map_err(|source| Error::Sound {
+                path: path.into(),
+                source,
+            })?;
+        self.sound_handles.push(Some(handle));
+        self.instance_handles.push(vec![]);
+        Ok(SoundID(self.sound_handles.len() - 1))
+    }
+
+    // Takes up the next SoundID with a sound that never plays, e.g. in place
+    // of one that didn't load, so the IDs after it stay the same
+    pub fn add_silent(&mut self) -> SoundID {
+        self.sound_handles.push(None);
+        self.instance_handles.push(vec![]);
+        SoundID(self.sound_handles.len() - 1)
+    }
+
     pub fn play(
         &mut self,
         id: SoundID,
@@ -49,12 +75,15 @@ impl Audio {
     ) {
         self.remove_stopped_instances();
         let settings = InstanceSettings::default();
+        let sound = match self.sound_handles[id.0].as_mut() {
+            Some(sound) => sound,
+            None => return,
+        };
         if self.instance_handles[id.0].is_empty() {
             // if sound is not currently playing, play it and add the instance to self
             let instance_handle = if !loops {
-                self.sound_handles[id.0].play(settings)
+                sound.play(settings)
             } else {
-                let sound = &self.sound_handles[id.0];
                 let arrangement = Arrangement::new_loop(sound, LoopArrangementSettings::default());
                 let mut arrangement_handle = self.manager.add_arrangement(arrangement).unwrap();
                 arrangement_handle.play(InstanceSettings::default())
@@ -67,7 +96,7 @@ impl Audio {
             match action {
                 AlreadyPlayingAction::Play => {
                     // play sound and add the instance to self
-                    if let Ok(instance_handle) = self.sound_handles[id.0].play(settings) {
+                    if let Ok(instance_handle) = sound.play(settings) {
                         self.instance_handles[id.0].push(instance_handle);
                     }
                 }
@@ -90,9 +119,13 @@ impl Audio {
         if let Some(tween) = fade_tween {
             settings.fade_tween(tween);
         }
+        let sound = match self.sound_handles[id.0].as_mut() {
+            Some(sound) => sound,
+            None => return,
+        };
         // stop sound and remove all instances
-        let stop = self.sound_handles[id.0].stop(settings);
-        if self.sound_handles[id.0].stop(settings).is_ok() {
+        let stop = sound.stop(settings);
+        if sound.stop(settings).is_ok() {
             self.instance_handles[id.0] = vec![];
         };
     }
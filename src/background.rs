@@ -1,16 +1,63 @@
+use crate::animation::{Animation, AnimationState};
+use crate::screen::premultiply;
 use crate::texture::Texture;
-use crate::types::Rect;
+use crate::types::{Rect, Rgba};
 use crate::Vec2f;
+use rand::Rng;
 use std::rc::Rc;
 
+// The shades starfield's little stars come in, dimmest first; cycle
+// through them to make the stars twinkle
+pub const STAR_SHADES: [Rgba; 4] = [
+    Rgba(90, 90, 110, 255),
+    Rgba(150, 150, 170, 255),
+    Rgba(205, 205, 220, 255),
+    Rgba(255, 255, 255, 255),
+];
+
+// Palette cycling: every `period` ticks, each pixel that started out as one
+// of `colors` moves on to the next one (and the last goes back to the first),
+// so a run of shades looks like it's flowing, flickering or twinkling.
+// A color can be listed more than once, e.g. to go back and forth.
+#[derive(PartialEq, Clone, Debug)]
+pub struct ColorCycle {
+    pub colors: Vec<Rgba>,
+    pub period: usize,
+}
+
+impl ColorCycle {
+    pub fn new(colors: Vec<Rgba>, period: usize) -> Self {
+        Self { colors, period }
+    }
+}
+
+struct Cycling {
+    cycle: ColorCycle,
+    // Byte offset of each cycling pixel, and which color it started as
+    pixels: Vec<(usize, usize)>,
+    step: usize,
+}
+
 pub struct Background {
+    // As loaded, and maybe shared; color cycles only ever change `image`
+    source: Rc<Texture>,
+    // How many times source had been reloaded when image was made from it
+    reloads: usize,
+    // What gets drawn: source itself, or a copy once colors start cycling
     image: Rc<Texture>,
     frame: Rect,
+    // Frames are rects in image, like a sprite's
+    animation: Option<Rc<Animation>>,
+    animation_state: AnimationState,
+    cycles: Vec<Cycling>,
+    ticks: usize,
 }
 
 impl Background {
     pub fn new(image: &Rc<Texture>, width: usize, height: usize) -> Self {
         Self {
+            source: Rc::clone(image),
+            reloads: image.reloads(),
             image: Rc::clone(image),
             frame: Rect {
                 x: 0,
@@ -18,7 +65,157 @@ impl Background {
                 h: height as u16,
                 w: width as u16,
             },
+            animation: None,
+            animation_state: AnimationState::new(),
+            cycles: vec![],
+            ticks: 0,
+        }
+    }
+
+    // Plays the animation's frames from image, one tick per `update`
+    pub fn animated(image: &Rc<Texture>, animation: &Rc<Animation>) -> Self {
+        let first = animation.current_frame(0);
+        let mut bg = Self::new(image, first.w as usize, first.h as usize);
+        bg.animation = Some(Rc::clone(animation));
+        bg.frame = Rect {
+            x: first.x as i32,
+            y: first.y as i32,
+            w: first.w,
+            h: first.h,
+        };
+        bg
+    }
+
+    // Starts cycling the pixels that are in cycle.colors right now.
+    // Several cycles can run at once as long as they don't share colors.
+    pub fn cycle_colors(&mut self, cycle: ColorCycle) {
+        let pixels = cycling_pixels(&self.source, &cycle);
+        self.cycles.push(Cycling {
+            cycle,
+            pixels,
+            step: 0,
+        });
+    }
+
+    // Call once per tick to run the animation and color cycles.
+    // Returns whether it looks any different now.
+    pub fn update(&mut self) -> bool {
+        self.ticks += 1;
+        let before = self.frame;
+        // The source was reloaded, so start over from its new pixels
+        let reloaded = self.source.reloads() != self.reloads;
+        if reloaded {
+            self.reloads = self.source.reloads();
+            self.image = Rc::clone(&self.source);
+            for c in self.cycles.iter_mut() {
+                c.pixels = cycling_pixels(&self.source, &c.cycle);
+                c.step = 0;
+            }
+        }
+        if let Some(anim) = &self.animation {
+            let state = &mut self.animation_state;
+            state.current_tick += 1;
+            if anim.looping {
+                state.current_tick %= anim.duration.max(1);
+            } else if state.current_tick > anim.duration {
+                state.done = true;
+            }
+            let f = anim.current_frame(state.current_tick);
+            self.frame = Rect {
+                x: f.x as i32,
+                y: f.y as i32,
+                w: f.w,
+                h: f.h,
+            };
+        }
+        let ticks = self.ticks;
+        let due: Vec<usize> = (0..self.cycles.len())
+            .filter(|&i| {
+                let c = &self.cycles[i].cycle;
+                c.period > 0 && !c.colors.is_empty() && ticks.is_multiple_of(c.period)
+            })
+            .collect();
+        if due.is_empty() {
+            return reloaded || self.frame != before;
+        }
+        // Copies the image first if it's still the source
+        let image = Rc::make_mut(&mut self.image).buffer_mut();
+        for i in due {
+            let c = &mut self.cycles[i];
+            let n = c.cycle.colors.len();
+            c.step = (c.step + 1) % n;
+            let shades: Vec<[u8; 4]> = c.cycle.colors.iter().map(|&c| premultiply(c)).collect();
+            for &(at, start) in c.pixels.iter() {
+                image[at..(at + 4)].copy_from_slice(&shades[(start + c.step) % n]);
+            }
+        }
+        true
+    }
+
+    // A sky fading through `stops` (as for Screen::linear_gradient) from
+    // the top down
+    pub fn sky(width: usize, height: usize, stops: &[(f32, Rgba)]) -> Self {
+        let mut image = Texture::blank(width, height);
+        image.screen(Vec2f(0.0, 0.0)).linear_gradient(
+            Rect {
+                x: 0,
+                y: 0,
+                w: width as u16,
+                h: height as u16,
+            },
+            Vec2f(0.0, 0.0),
+            Vec2f(0.0, height as f32),
+            stops,
+        );
+        Self::new(&Rc::new(image), width, height)
+    }
+
+    // `count` randomly scattered stars over a sky (or over nothing, if
+    // `sky` is empty, to lay over something else).  Stars going off one edge
+    // come back on the other, so it tiles without seams.
+    pub fn starfield(width: usize, height: usize, count: usize, sky: &[(f32, Rgba)]) -> Self {
+        let mut image = Texture::blank(width, height);
+        {
+            let mut screen = image.screen(Vec2f(0.0, 0.0));
+            if !sky.is_empty() {
+                let all = screen.bounds();
+                screen.linear_gradient(all, Vec2f(0.0, 0.0), Vec2f(0.0, height as f32), sky);
+            }
+            let mut rng = rand::thread_rng();
+            let (w, h) = (width as f32, height as f32);
+            for _ in 0..count {
+                let x = rng.gen_range(0.0..w);
+                let y = rng.gen_range(0.0..h);
+                let shade = rng.gen_range(0..STAR_SHADES.len());
+                // Mostly single pixels, with the odd bright one that glows
+                if !rng.gen_ratio(1, 12) {
+                    screen.draw_at(STAR_SHADES[shade], Vec2f(x, y));
+                    continue;
+                }
+                for &ox in [-w, 0.0, w].iter() {
+                    for &oy in [-h, 0.0, h].iter() {
+                        let p = Vec2f(x + ox, y + oy);
+                        let glow = Rect {
+                            x: p.0 as i32 - 4,
+                            y: p.1 as i32 - 4,
+                            w: 9,
+                            h: 9,
+                        };
+                        screen.radial_gradient(
+                            glow,
+                            p,
+                            4.5,
+                            &[
+                                (0.0, Rgba(200, 210, 255, 140)),
+                                (1.0, Rgba(200, 210, 255, 0)),
+                            ],
+                        );
+                        screen.fill_circle(p, 1.2, Rgba(255, 255, 255, 255));
+                    }
+                }
+            }
         }
+        Self::new(&Rc::new(image), width, height)
     }
 
     pub fn tick_right(&mut self, w: usize) {
@@ -28,8 +225,93 @@ impl Background {
     }
 }
 
+// Byte offset of each pixel in image that's one of cycle's colors, and
+// which color it is
+fn cycling_pixels(image: &Texture, cycle: &ColorCycle) -> Vec<(usize, usize)> {
+    let shades: Vec<[u8; 4]> = cycle.colors.iter().map(|&c| premultiply(c)).collect();
+    image
+        .buffer()
+        .chunks_exact(4)
+        .enumerate()
+        .filter_map(|(i, px)| shades.iter().position(|s| s[..] == *px).map(|c| (i * 4, c)))
+        .collect()
+}
+
+// One layer of a parallax backdrop.  The camera moving one pixel moves the
+// layer `factor` pixels on screen: 0.0 stays put like a far-off sky, 1.0
+// moves with the world, and in between looks somewhere in between.
+pub struct ParallaxLayer {
+    pub background: Background,
+    pub factor: Vec2f,
+    // Repeat endlessly across and/or down instead of showing once
+    pub wrap: (bool, bool),
+    // Drift by itself, in pixels per tick, like clouds
+    pub velocity: Vec2f,
+    // Where the layer sits when the camera's at the world origin
+    pub offset: Vec2f,
+}
+
+impl ParallaxLayer {
+    // Wraps both ways and doesn't drift
+    pub fn new(background: Background, factor: Vec2f) -> Self {
+        Self {
+            background,
+            factor,
+            wrap: (true, true),
+            velocity: Vec2f(0.0, 0.0),
+            offset: Vec2f(0.0, 0.0),
+        }
+    }
+
+    // Where the layer's top left is in the world when looking from camera
+    fn origin(&self, camera: Vec2f) -> Vec2f {
+        Vec2f(
+            camera.0 * (1.0 - self.factor.0) + self.offset.0,
+            camera.1 * (1.0 - self.factor.1) + self.offset.1,
+        )
+    }
+}
+
+// Layers draw first to last, so put the farthest first
+#[derive(Default)]
+pub struct Parallax {
+    pub layers: Vec<ParallaxLayer>,
+}
+
+impl Parallax {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, layer: ParallaxLayer) {
+        self.layers.push(layer);
+    }
+
+    // Call once per tick for drifting layers and to update each layer's background
+    pub fn update(&mut self) {
+        for l in self.layers.iter_mut() {
+            l.background.update();
+            l.offset.0 += l.velocity.0;
+            l.offset.1 += l.velocity.1;
+            // Keep the offset small so wrapping layers don't lose precision
+            let (w, h) = (l.background.frame.w as f32, l.background.frame.h as f32);
+            if l.wrap.0 && w > 0.0 {
+                l.offset.0 = l.offset.0.rem_euclid(w);
+            }
+            if l.wrap.1 && h > 0.0 {
+                l.offset.1 = l.offset.1.rem_euclid(h);
+            }
+        }
+    }
+}
+
 pub trait DrawBackgroundExt {
     fn draw_background(&mut self, b: &Background);
+    // Repeats the background over everything on screen, lined up with the
+    // world's origin, so it scrolls along with the camera
+    fn draw_background_tiled(&mut self, b: &Background);
+    // Every layer, moved for the screen's position
+    fn draw_parallax(&mut self, p: &Parallax);
 }
 
 use crate::screen::Screen;
@@ -40,4 +322,49 @@ impl<'fb> DrawBackgroundExt for Screen<'fb> {
         let frame = b.frame;
         self.bitblt(&b.image, frame, Vec2f(0.0, 0.0));
     }
+
+    fn draw_background_tiled(&mut self, b: &Background) {
+        draw_repeated(self, b, Vec2f(0.0, 0.0), (true, true));
+    }
+
+    fn draw_parallax(&mut self, p: &Parallax) {
+        let camera = self.position();
+        for l in p.layers.iter() {
+            draw_repeated(self, &l.background, l.origin(camera), l.wrap);
+        }
+    }
+}
+
+// Draws b with its top left at origin, repeated over the screen along
+// whichever axes wrap
+fn draw_repeated(screen: &mut Screen, b: &Background, origin: Vec2f, wrap: (bool, bool)) {
+    let frame = b.frame;
+    if frame.w == 0 || frame.h == 0 {
+        return;
+    }
+    let (fw, fh) = (frame.w as f32, frame.h as f32);
+    let view = screen.bounds();
+    // The first copy at or before the screen's edge, and how far to go
+    let (x0, x1) = if wrap.0 {
+        let x0 = origin.0 + ((view.x as f32 - origin.0) / fw).floor() * fw;
+        (x0, (view.x + view.w as i32) as f32)
+    } else {
+        (origin.0, origin.0 + 1.0)
+    };
+    let (y0, y1) = if wrap.1 {
+        let y0 = origin.1 + ((view.y as f32 - origin.1) / fh).floor() * fh;
+        (y0, (view.y + view.h as i32) as f32)
+    } else {
+        (origin.1, origin.1 + 1.0)
+    };
+    let mut y = y0;
+    while y < y1 {
+        let mut x = x0;
+        while x < x1 {
+            // Whole pixels, so the copies butt up against each other exactly
+            screen.bitblt(&b.image, frame, Vec2f(x.floor(), y.floor()));
+            x += fw;
+        }
+        y += fh;
+    }
 }
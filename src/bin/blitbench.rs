@@ -0,0 +1,97 @@
+// Measures how fast bitblt composites, without opening a window.
+// Run with `cargo run --release --bin blitbench`.
+use anim2d::canvas::Canvas;
+use anim2d::screen::Screen;
+use anim2d::texture::Texture;
+use anim2d::tiles::*;
+use anim2d::types::*;
+use std::hint::black_box;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+// Same size as game2's window
+const WIDTH: usize = 512;
+const HEIGHT: usize = 1024;
+const FRAMES: usize = 300;
+
+fn main() -> anim2d::Result<()> {
+    let tiles = Rc::new(Texture::with_file(Path::new("content/tiles_dig.png"))?);
+    let sprite = Texture::with_file(Path::new("content/scubasprite.png"))?;
+    let tileset = Rc::new(Tileset::new(
+        vec![
+            Tile {
+                solid: false,
+                explode: false,
+                destructible: true,
+            };
+            6
+        ],
+        &tiles,
+    )?);
+    // Screen-filling maps, the way game2 draws them every frame
+    let map = Tilemap::new(
+        Vec2f(0.0, 0.0),
+        (16, 32),
+        &tileset,
+        Tilemap::generate_rand_map_2(0.8, (16, 32), TileID(0), TileID(1)),
+    )?;
+    let mut canvas = Canvas::new(WIDTH, HEIGHT);
+
+    let pixels = WIDTH * HEIGHT;
+    let t = run(&mut canvas, |screen| map.draw(screen));
+    report("tilemap (opaque tiles)", t, pixels);
+
+    let t = run(&mut canvas, |screen| {
+        for y in (0..HEIGHT).step_by(64) {
+            for x in (0..WIDTH).step_by(64) {
+                screen.bitblt(
+                    &sprite,
+                    Rect {
+                        x: 0,
+                        y: 0,
+                        w: 64,
+                        h: 64,
+                    },
+                    Vec2f(x as f32, y as f32),
+                );
+            }
+        }
+    });
+    report("sprites (alpha blended)", t, pixels);
+
+    let t = run(&mut canvas, |screen| {
+        screen.rect_blend(
+            Rect {
+                x: 0,
+                y: 0,
+                w: WIDTH as u16,
+                h: HEIGHT as u16,
+            },
+            Rgba(115, 115, 115, 160),
+        )
+    });
+    report("rect_blend (translucent)", t, pixels);
+    Ok(())
+}
+
+fn run<F: FnMut(&mut Screen)>(canvas: &mut Canvas, mut draw: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        let mut screen = canvas.screen(Vec2f(0.0, 0.0));
+        screen.clear(Rgba(80, 80, 80, 255));
+        draw(&mut screen);
+        black_box(screen.framebuffer.as_ptr());
+    }
+    start.elapsed()
+}
+
+fn report(name: &str, t: Duration, pixels_per_frame: usize) {
+    let secs = t.as_secs_f64();
+    println!(
+        "{:<26} {:>8.3} ms/frame {:>8.1} Mpx/s",
+        name,
+        secs * 1000.0 / FRAMES as f64,
+        (pixels_per_frame * FRAMES) as f64 / secs / 1.0e6
+    );
+}
@@ -1,6 +1,5 @@
 use kira::manager::AudioManager;
 use kira::manager::AudioManagerSettings;
-use kira::sound::SoundSettings;
 use pixels::{Pixels, SurfaceTexture};
 use std::path::Path;
 use std::rc::Rc;
@@ -8,14 +7,20 @@ use std::time::Instant;
 use winit::dpi::LogicalSize;
 use winit::event::{Event, VirtualKeyCode};
 use winit::event_loop::{ControlFlow, EventLoop};
-use winit::window::WindowBuilder;
+use winit::window::{Fullscreen, WindowBuilder};
 use winit_input_helper::WinitInputHelper;
 
 use anim2d::animation::*;
+use anim2d::assets::Assets;
 use anim2d::audio::*;
 use anim2d::background::*;
+use anim2d::camera::Camera;
+use anim2d::capture::{self, RecordFormat, Recorder};
 use anim2d::collision::*;
+use anim2d::debug::DebugOverlay;
 use anim2d::obstacle::*;
+use anim2d::postfx::PostFx;
+use anim2d::queue::RenderQueue;
 use anim2d::screen::Screen;
 use anim2d::sprite::*;
 use anim2d::text::*;
@@ -24,6 +29,7 @@ use anim2d::tiles::Tilemap;
 use anim2d::tiles::*;
 use anim2d::types::Vec2f;
 use anim2d::types::*;
+use anim2d::watch::Watcher;
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 enum Mode {
@@ -32,22 +38,41 @@ enum Mode {
     EndGame,
 }
 
+// Which loaded sound is which
+struct Sounds {
+    title: SoundID,
+    gameplay: SoundID,
+    collision: SoundID,
+    end: SoundID,
+}
+
 struct GameState {
-    animations: Vec<Rc<Animation>>,
-    textures: Vec<Rc<Texture>>,
+    assets: Assets,
+    sounds: Sounds,
     sprites: Vec<Sprite>,
     backgrounds: Vec<Background>,
     curr_location: usize,
     obstacles: Vec<Obstacle>,
-    bg_tilemaps: Vec<Rc<Tilemap>>,
+    // Stars and planets, drifting by slower than the meteors
+    backdrop: Parallax,
+    planet_maps: Vec<Tilemap>,
     obstacle_tilemaps: Vec<Rc<Tilemap>>,
-    camera_position: Vec2f,
+    camera: Camera,
     camera_speed: f32,
     mode: Mode,
     font: Rc<Font>,
     level: usize,
     text: Vec<Text>,
+    story: Texture,
+    story_pos: Vec2f,
     audio: Audio,
+    debug: DebugOverlay,
+    // Where the mouse is in the framebuffer, if it's over the game
+    mouse: Option<Vec2f>,
+    postfx: PostFx,
+    queue: RenderQueue,
+    // Where we're going once the fade out finishes
+    next_mode: Option<Mode>,
 }
 
 // seconds per frame
@@ -56,6 +81,12 @@ const DT: f64 = 1.0 / 60.0;
 const WIDTH: usize = 512;
 const HEIGHT: usize = 256;
 const DEPTH: usize = 4;
+// How long switching modes takes to fade out, and then back in
+const FADE_TICKS: usize = 20;
+// Where screenshots and recordings go
+const CAPTURE_DIR: &str = "captures";
+// Record every third frame, i.e. 20 frames a second at 60Hz
+const RECORD_EVERY: usize = 3;
 const PLAYER_WIDTH: u16 = 32;
 const PLAYER_HEIGHT: u16 = 32;
 const FONT_SIZE: f32 = 20.0;
@@ -67,8 +98,21 @@ const SPRITE_INITIAL_VX: f32 = 0.5;
 const SPRITE_INITIAL_VY: f32 = 0.0;
 const LEVEL_WIDTH: usize = 2048;
 const METEOR_START: f32 = 1400.0;
+// Which of the backdrop's layers has the planets
+const PLANETS_LAYER: usize = 2;
+// Render queue layers, back to front
+const METEOR_LAYER: i32 = 0;
+const SPRITE_LAYER: i32 = 1;
 
 fn main() {
+    // Tell whoever's running it what went wrong, e.g. which file is missing
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run() -> anim2d::Result<()> {
     let event_loop = EventLoop::new();
     let mut input = WinitInputHelper::new();
     let window = {
@@ -77,7 +121,6 @@ fn main() {
             .with_title("Race to Save the Beached Whale")
             .with_inner_size(size)
             .with_min_inner_size(size)
-            .with_resizable(false)
             .build(&event_loop)
             .unwrap()
     };
@@ -86,19 +129,46 @@ fn main() {
         let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
         Pixels::new(WIDTH as u32, HEIGHT as u32, surface_texture).unwrap()
     };
-    let astronaut = Rc::new(Texture::with_file(Path::new("content/Astronaut-Sheet.png")));
-    let tex = Rc::new(Texture::with_file(Path::new("content/spacetiles.png")));
-    let start = Background::new(
-        &Rc::new(Texture::with_file(Path::new("content/startscreen.png"))),
-        WIDTH,
-        HEIGHT,
-    );
-    let end = Background::new(
-        &Rc::new(Texture::with_file(Path::new("content/endscreen.png"))),
-        WIDTH,
-        HEIGHT,
-    );
-    let tileset = Rc::new(Tileset::new(
+    let mut assets = Assets::new("content");
+    let astronaut = assets.sheet("Astronaut-Sheet.json")?;
+    let title_image = assets.texture("startscreen.png")?;
+    let end_image = assets.texture("endscreen.png")?;
+    let mut start = Background::new(assets.get(title_image), WIDTH, HEIGHT);
+    let mut end = Background::new(assets.get(end_image), WIDTH, HEIGHT);
+    // Same art on both: the meteor's flames flicker and the stars twinkle
+    for bg in [&mut start, &mut end] {
+        bg.cycle_colors(ColorCycle::new(
+            vec![
+                Rgba(231, 41, 1, 255),
+                Rgba(231, 84, 1, 255),
+                Rgba(228, 112, 22, 255),
+                Rgba(231, 139, 1, 255),
+            ],
+            6,
+        ));
+        bg.cycle_colors(ColorCycle::new(
+            vec![
+                Rgba(231, 187, 68, 255),
+                Rgba(244, 212, 118, 255),
+                Rgba(255, 238, 186, 255),
+                Rgba(244, 212, 118, 255),
+                Rgba(231, 187, 68, 255),
+                Rgba(196, 150, 48, 255),
+            ],
+            10,
+        ));
+    }
+    // Deep space, the same color as behind the planets in spacetiles.png
+    let far_stars = Background::starfield(WIDTH, HEIGHT, 160, &[(0.0, Rgba(2, 2, 27, 255))]);
+    let mut near_stars = Background::starfield(WIDTH, HEIGHT, 40, &[]);
+    let [dim, mid, bright, brightest] = STAR_SHADES;
+    near_stars.cycle_colors(ColorCycle::new(
+        vec![dim, mid, bright, brightest, bright, mid],
+        8,
+    ));
+    let tileset = assets.tileset(
+        "space",
+        "spacetiles.png",
         vec![
             Tile {
                 solid: false,
@@ -146,92 +216,87 @@ fn main() {
                 destructible: true,
             }, // transparent
         ],
-        &tex,
-    ));
+    )?;
+    let tileset = Rc::clone(assets.get(tileset));
     let map1 = Tilemap::new(
         Vec2f(0.0, 0.0),
         (16, 8),
         &tileset,
         vec![
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0,
-            3, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5, 6, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0,
-            0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0,
+            8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8,
+            3, 4, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 5, 6, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8,
+            8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8,
+            8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 2, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8,
+            8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8,
         ],
-    );
+    )?;
 
     let map2 = Tilemap::new(
         Vec2f(512.0, 0.0),
         (16, 8),
         &tileset,
         vec![
-            0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 0,
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0,
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0,
-            0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 3, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-            5, 6, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0,
+            8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 2, 8, 8, 8, 8, 8, 8, 8, 8,
+            8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8,
+            8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8,
+            8, 8, 8, 8, 8, 8, 8, 2, 8, 8, 8, 8, 8, 3, 4, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8,
+            5, 6, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8,
         ],
-    );
+    )?;
 
     let map3 = Tilemap::new(
         Vec2f(1024.0, 0.0),
         (16, 8),
         &tileset,
         vec![
-            1, 0, 0, 0, 2, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-            0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 3,
-            4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5, 6, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0,
-            0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0,
+            8, 8, 8, 8, 2, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8,
+            8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 3,
+            4, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 5, 6, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8,
+            8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 2, 8, 8, 8, 8, 8,
+            8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8,
         ],
-    );
+    )?;
     let map4 = Tilemap::new(
         Vec2f(1536.0, 0.0),
         (16, 8),
         &tileset,
         vec![
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 1, 0, 0,
-            0, 0, 0, 1, 0, 0, 0, 0, 3, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5, 6, 0, 0, 0,
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0,
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8,
+            8, 8, 8, 8, 8, 8, 8, 8, 3, 4, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 5, 6, 8, 8, 8,
+            8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8,
+            8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 2, 8, 8, 8, 8, 8, 8, 8,
+            8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8,
         ],
-    );
-    let mut audio_manager = AudioManager::new(AudioManagerSettings::default()).unwrap();
-    let startscreen = audio_manager
-        .load_sound(
-            "content/Startscreen.wav",
-            SoundSettings::default(), // SoundSettings::new().semantic_duration(Tempo(128.0).beats_to_seconds(8.0)),
-        )
-        .unwrap();
-    let gameplay = audio_manager
-        .load_sound(
-            "content/GamePlay.mp3",
-            SoundSettings::default(), // SoundSettings::new().semantic_duration(Tempo(128.0).beats_to_seconds(8.0)),
-        )
-        .unwrap();
-    let endscreen = audio_manager
-        .load_sound(
-            "content/endscreen.mp3",
-            SoundSettings::default(), // SoundSettings::new().semantic_duration(Tempo(128.0).beats_to_seconds(8.0)),
-        )
-        .unwrap();
-    let collision = audio_manager
-        .load_sound(
-            "content/collision.mp3",
-            SoundSettings::default(), // SoundSettings::new().semantic_duration(Tempo(128.0).beats_to_seconds(8.0)),
-        )
-        .unwrap();
-    let sound_handles = vec![startscreen, gameplay, collision, endscreen];
-    let audio = Audio::new(audio_manager, sound_handles);
+    )?;
+    let planet_maps = vec![map1, map2, map3, map4];
+    let mut backdrop = Parallax::new();
+    // In PLANETS_LAYER order
+    for (bg, factor) in [
+        (far_stars, 0.1),
+        (near_stars, 0.25),
+        (bake_planets(&planet_maps), 0.5),
+    ] {
+        let mut layer = ParallaxLayer::new(bg, Vec2f(factor, 0.0));
+        layer.wrap = (true, false);
+        backdrop.push(layer);
+    }
+    let mut audio = Audio::new(AudioManager::new(AudioManagerSettings::default())?, vec![]);
+    let sounds = Sounds {
+        title: assets.sound(&mut audio, "Startscreen.mp3"),
+        gameplay: assets.sound(&mut audio, "GamePlay.mp3"),
+        collision: assets.sound(&mut audio, "collision.mp3"),
+        end: assets.sound(&mut audio, "endscreen.mp3"),
+    };
+    for path in assets.missing() {
+        eprintln!("Couldn't load {}, carrying on without it", path.display());
+    }
 
     let meteors = Tilemap::new(
         Vec2f(METEOR_START as f32, 0.0),
         (64, 8),
         &tileset,
         Tilemap::generate_rand_map_2(0.95, (64, 8), TileID(8), TileID(7)),
-    );
+    )?;
 
     let meteors2 = Tilemap::new(
         Vec2f(METEOR_START + (meteors.dims.0 * TILE_SZ) as f32, 0.0),
@@ -239,17 +304,17 @@ fn main() {
         (64, 8),
         &tileset,
         Tilemap::generate_rand_map_2(0.95, (64, 8), TileID(8), TileID(7)),
-    );
-
-    let walk_frames = Rectf::create_frames(3, 7, PLAYER_WIDTH, PLAYER_HEIGHT);
-    let walk_timing = vec![5, 5, 5, 5];
-
-    let walk = Rc::new(Animation::new(walk_frames, walk_timing, true));
-    let animations = vec![walk];
-    let animations_clone = animations.clone();
+    )?;
+
+    let walk = assets
+        .find::<Animation>("walk")
+        .ok_or_else(|| anim2d::Error::Sheet {
+            path: assets.resolve("Astronaut-Sheet.json"),
+            why: "there's no walk tag".to_string(),
+        })?;
     let player = Sprite::new(
-        &astronaut,
-        &animations_clone[0],
+        assets.get(astronaut),
+        assets.get(walk),
         AnimationState::new(),
         Rectf {
             x: 60.0,
@@ -265,9 +330,8 @@ fn main() {
     // let player_y = player.position.1;
     let sprites = vec![player];
 
-    let font = Rc::new(Font {
-        image: Rc::new(Texture::with_file(Path::new("content/ascii.png"))),
-    });
+    let font = assets.font("ascii.png")?;
+    let font = Rc::clone(assets.get(font));
     let text1 = Text::new(Rc::clone(&font), "It is March 25, 2021.", Vec2f(75.0, 50.0));
 
     let text2 = Text::new(
@@ -333,27 +397,53 @@ fn main() {
         ),
     );
 
-    let display_text = vec![
-        text1, text2, text3, text4, text5, text6, text7, text8, text9, text10, text11, text12,
+    // The story never changes, so write it into a texture once and blit
+    // that instead of every letter every frame
+    let mut story_text = [
+        text1, text2, text3, text4, text5, text6, text7, text8, text9, text10, text11,
     ];
+    let story_x = story_text
+        .iter()
+        .map(|t| t.pos.0)
+        .fold(f32::INFINITY, f32::min);
+    let story_end = story_text
+        .iter()
+        .map(|t| t.pos.0 + t.length as f32)
+        .fold(story_x, f32::max);
+    let mut story = Texture::blank((story_end - story_x).ceil() as usize, HEIGHT);
+    {
+        let mut screen = story.screen(Vec2f(story_x, 0.0));
+        for t in story_text.iter_mut() {
+            screen.draw_text(t);
+        }
+    }
+    let display_text = vec![text12];
 
     let mut state = GameState {
         // initial game state...
-        animations,
+        assets,
+        sounds,
         sprites,
-        textures: vec![astronaut],
         backgrounds: vec![start, end],
         curr_location: 0,
         obstacles: vec![],
-        bg_tilemaps: vec![Rc::new(map1), Rc::new(map2), Rc::new(map3), Rc::new(map4)],
+        backdrop,
+        planet_maps,
         obstacle_tilemaps: vec![Rc::new(meteors), Rc::new(meteors2)],
-        camera_position: Vec2f(0.0, 0.0),
+        camera: Camera::new(Vec2f(0.0, 0.0), WIDTH, HEIGHT),
         camera_speed: START_SPEED,
         mode: Mode::TitleScreen,
         font,
         level: 0,
         text: display_text,
+        story,
+        story_pos: Vec2f(story_x, 0.0),
         audio,
+        debug: DebugOverlay::new(),
+        mouse: None,
+        postfx: PostFx::new(),
+        queue: RenderQueue::new(),
+        next_mode: None,
     };
 
     let mut contacts: Vec<Contact> = vec![];
@@ -365,23 +455,50 @@ fn main() {
     let start = Instant::now();
     // Track end of the last frame
     let mut since = Instant::now();
+    // Screenshots are taken when the next frame is drawn
+    let mut take_screenshot = false;
+    let mut recorder: Option<Recorder> = None;
+    // Debug builds pick up art and sprite sheets that are edited while the
+    // game runs (the maps are still written out in code)
+    let mut watcher = if cfg!(debug_assertions) {
+        Some(Watcher::new(&["content"]))
+    } else {
+        None
+    };
 
     event_loop.run(move |event, _, control_flow| {
         // Draw the current frame
         if let Event::RedrawRequested(_) = event {
+            state.debug.frame();
             let mut screen = Screen::wrap(
                 pixels.get_frame(),
                 WIDTH,
                 HEIGHT,
                 DEPTH,
-                state.camera_position,
+                state.camera.screen_position(),
             );
             screen.clear(Rgba(0, 0, 0, 0));
 
             draw_game(&mut state, &mut screen);
+            state.postfx.apply(&mut screen);
+
+            if take_screenshot {
+                take_screenshot = false;
+                match capture::screenshot(&screen, Path::new(CAPTURE_DIR)) {
+                    Ok(path) => println!("Saved {}", path.display()),
+                    Err(e) => eprintln!("Couldn't save screenshot: {}", e),
+                }
+            }
+            if let Some(r) = recorder.as_mut() {
+                r.capture(&screen);
+            }
 
             // Flip buffers
             if pixels.render().is_err() {
+                // Exiting doesn't wait for the recording thread
+                if let Some(r) = recorder.take() {
+                    finish_recording(r);
+                }
                 *control_flow = ControlFlow::Exit;
                 return;
             }
@@ -394,13 +511,48 @@ fn main() {
         if input.update(event) {
             // Close events
             if input.key_pressed(VirtualKeyCode::Escape) || input.quit() {
+                // Exiting doesn't wait for the recording thread
+                if let Some(r) = recorder.take() {
+                    finish_recording(r);
+                }
                 *control_flow = ControlFlow::Exit;
                 return;
             }
-            // Resize the window if needed
+            // Show or hide colliders and frame stats
+            if input.key_pressed(VirtualKeyCode::F3) {
+                state.debug.toggle();
+            }
+            // F12 saves a screenshot; F11 records a gif and F10 a PNG sequence
+            if input.key_pressed(VirtualKeyCode::F12) {
+                take_screenshot = true;
+            }
+            if input.key_pressed(VirtualKeyCode::F11) {
+                toggle_recording(&mut recorder, RecordFormat::Gif);
+            }
+            if input.key_pressed(VirtualKeyCode::F10) {
+                toggle_recording(&mut recorder, RecordFormat::PngSequence);
+            }
+            // F9 switches between a window and fullscreen
+            if input.key_pressed(VirtualKeyCode::F9) {
+                if window.fullscreen().is_some() {
+                    window.set_fullscreen(None);
+                } else {
+                    window.set_fullscreen(Some(Fullscreen::Borderless(window.current_monitor())));
+                }
+            }
+            // Resize the window if needed; pixels scales the framebuffer up by
+            // whole numbers and centers it
             if let Some(size) = input.window_resized() {
                 pixels.resize(size.width, size.height);
             }
+            // Through the same scaling pixels draws with, so they line up
+            state.mouse = input
+                .mouse()
+                .and_then(|m| pixels.window_pos_to_pixel(m).ok())
+                .map(|(x, y)| Vec2f(x as f32, y as f32));
+        }
+        if let Some(w) = watcher.as_mut() {
+            reload_changed(&mut state, w);
         }
         // And the simulation "consumes" it
         while available_time >= DT {
@@ -419,6 +571,69 @@ fn main() {
     });
 }
 
+fn toggle_recording(recorder: &mut Option<Recorder>, format: RecordFormat) {
+    match recorder.take() {
+        Some(r) => finish_recording(r),
+        None => match Recorder::start(Path::new(CAPTURE_DIR), format, RECORD_EVERY) {
+            Ok(r) => {
+                println!("Recording to {}", r.path().display());
+                *recorder = Some(r);
+            }
+            Err(e) => eprintln!("Couldn't start recording: {}", e),
+        },
+    }
+}
+
+fn finish_recording(r: Recorder) {
+    let path = r.path().to_path_buf();
+    let dropped = r.dropped();
+    match r.finish() {
+        Ok(n) if dropped > 0 => println!(
+            "Saved {} frames to {} ({} dropped to keep up)",
+            n,
+            path.display(),
+            dropped
+        ),
+        Ok(n) => println!("Saved {} frames to {}", n, path.display()),
+        Err(e) => eprintln!("Couldn't save recording: {}", e),
+    }
+}
+
+// The maps just place the planets and moons; they get baked into one wide
+// image for the nearest backdrop layer
+fn bake_planets(maps: &[Tilemap]) -> Background {
+    let mut planets = Texture::blank(WIDTH * 4, HEIGHT);
+    {
+        let mut screen = planets.screen(Vec2f(0.0, 0.0));
+        for map in maps.iter() {
+            map.draw(&mut screen);
+        }
+    }
+    Background::new(&Rc::new(planets), WIDTH * 4, HEIGHT)
+}
+
+fn reload_changed(state: &mut GameState, watcher: &mut Watcher) {
+    let mut reloaded = false;
+    for path in watcher.poll() {
+        match state.assets.reload(&path) {
+            Ok(true) => {
+                println!("Reloaded {}", path.display());
+                reloaded = true;
+            }
+            Ok(false) => {}
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+    if reloaded {
+        // The tileset might have changed under the baked planets
+        state.backdrop.layers[PLANETS_LAYER].background = bake_planets(&state.planet_maps);
+        // And the walk cycle, if it was the astronaut's sheet
+        if let Some(walk) = state.assets.find::<Animation>("walk") {
+            state.sprites[0].animation = Rc::clone(state.assets.get(walk));
+        }
+    }
+}
+
 fn draw_game(state: &mut GameState, screen: &mut Screen) {
     // Call screen's drawing methods to render the game state
     screen.clear(Rgba(80, 80, 80, 255));
@@ -435,30 +650,18 @@ fn draw_game(state: &mut GameState, screen: &mut Screen) {
             screen.draw_text(&mut start_text);
         }
         Mode::GamePlay => {
+            let camera_position = state.camera.position();
             let screen_corners = vec![
-                Vec2f(state.camera_position.0, state.camera_position.1),
-                Vec2f(
-                    state.camera_position.0 + WIDTH as f32,
-                    state.camera_position.1,
-                ),
+                Vec2f(camera_position.0, camera_position.1),
+                Vec2f(camera_position.0 + WIDTH as f32, camera_position.1),
+                Vec2f(camera_position.0, camera_position.1 + HEIGHT as f32),
                 Vec2f(
-                    state.camera_position.0,
-                    state.camera_position.1 + HEIGHT as f32,
-                ),
-                Vec2f(
-                    state.camera_position.0 + WIDTH as f32,
-                    state.camera_position.1 + HEIGHT as f32,
+                    camera_position.0 + WIDTH as f32,
+                    camera_position.1 + HEIGHT as f32,
                 ),
             ];
-            let mut draw_bgmaps = vec![];
             let mut draw_obsmaps = vec![];
             for posn in screen_corners {
-                if let Some(i) = tile_map_at(posn, &state.bg_tilemaps) {
-                    let map = &state.bg_tilemaps[i];
-                    if !draw_bgmaps.contains(&map) {
-                        draw_bgmaps.push(map);
-                    }
-                }
                 if let Some(i) = tile_map_at(posn, &state.obstacle_tilemaps) {
                     let map = &state.obstacle_tilemaps[i];
                     if !draw_obsmaps.contains(&map) {
@@ -467,43 +670,59 @@ fn draw_game(state: &mut GameState, screen: &mut Screen) {
                 }
             }
 
-            for map in draw_bgmaps {
-                map.draw(screen);
+            screen.draw_parallax(&state.backdrop);
+            // The astronaut stays in front of the meteor belt
+            for s in state.sprites.iter() {
+                state.queue.push_sprite(SPRITE_LAYER, s);
             }
             for map in draw_obsmaps {
-                map.draw(screen);
+                state.queue.push_tilemap(METEOR_LAYER, map);
             }
 
             //infinite tilemaps
             update_tilemaps(
-                state.camera_position,
-                &mut state.bg_tilemaps,
-                false,
-                state.level,
-            );
-            update_tilemaps(
-                state.camera_position,
+                state.camera.position(),
                 &mut state.obstacle_tilemaps,
-                true,
                 state.level,
             );
 
-            for s in state.sprites.iter() {
-                screen.draw_sprite(s);
-            }
+            state.queue.flush(screen);
             for o in state.obstacles.iter() {
                 screen.draw_obstacle(o);
             }
 
             // Check screen position to update level
-            let start_pos = (state.camera_position.0 - METEOR_START as f32).max(0.0);
+            let start_pos = (state.camera.position().0 - METEOR_START as f32).max(0.0);
             if start_pos as usize / LEVEL_WIDTH != state.level {
                 new_level(state);
             }
 
+            screen.bitblt(
+                &state.story,
+                Rect {
+                    x: 0,
+                    y: 0,
+                    w: state.story.width as u16,
+                    h: state.story.height as u16,
+                },
+                state.story_pos,
+            );
             for text in &mut state.text {
                 screen.draw_text(text);
             }
+
+            for map in state.obstacle_tilemaps.iter() {
+                state.debug.draw_tilemap(screen, map);
+            }
+            state.debug.draw_sprites(screen, &state.sprites);
+            state.debug.draw_obstacles(screen, &state.obstacles);
+            state
+                .debug
+                .draw_points(screen, &collision_points(&state.sprites[0]));
+            if let Some(Vec2f(x, y)) = state.mouse {
+                let Vec2f(cx, cy) = screen.position();
+                state.debug.draw_points(screen, &[Vec2f(x + cx, y + cy)]);
+            }
         }
         Mode::EndGame => {
             screen.draw_background(&state.backgrounds[1]);
@@ -515,40 +734,42 @@ fn draw_game(state: &mut GameState, screen: &mut Screen) {
                 Vec2f(70.0, 130.0),
             );
 
-            screen.rect(
+            let red = Rgba(215, 0, 0, 255);
+            screen.fill_rounded_rect(
                 Rect {
                     w: 164,
                     h: 30,
                     x: 164,
                     y: 82,
                 },
-                Rgba(215, 0, 0, 255),
-            );
-            screen.line(
-                Vec2f(160.0, 82.0),
-                Vec2f(160.0, 112.0),
-                Rgba(215, 0, 0, 255),
-            );
-            screen.line(
-                Vec2f(156.0, 82.0),
-                Vec2f(156.0, 112.0),
-                Rgba(215, 0, 0, 255),
+                4.0,
+                red,
             );
-            screen.line(
-                Vec2f(332.0, 82.0),
-                Vec2f(332.0, 112.0),
-                Rgba(215, 0, 0, 255),
-            );
-            screen.line(
-                Vec2f(336.0, 82.0),
-                Vec2f(336.0, 112.0),
-                Rgba(215, 0, 0, 255),
+            screen.rounded_rect(
+                Rect {
+                    w: 182,
+                    h: 40,
+                    x: 155,
+                    y: 77,
+                },
+                8.0,
+                2.0,
+                red,
             );
 
             screen.draw_text(&mut game_over);
             screen.draw_text(&mut try_again);
         }
     }
+    state.debug.draw_stats(screen, &state.font);
+}
+
+// Fade out, switch to mode, and fade back in
+fn change_mode(state: &mut GameState, mode: Mode) {
+    if state.next_mode.is_none() {
+        state.next_mode = Some(mode);
+        state.postfx.fade_out(Rgba(0, 0, 0, 255), FADE_TICKS);
+    }
 }
 
 fn update_game(
@@ -557,28 +778,52 @@ fn update_game(
     input: &WinitInputHelper,
     frame: usize,
 ) {
+    state.postfx.update();
+    // Backgrounds run their own animations
+    for bg in state.backgrounds.iter_mut() {
+        bg.update();
+    }
+    state.backdrop.update();
+    // Hold everything still while fading out, then switch over
+    if let Some(mode) = state.next_mode {
+        if state.postfx.fading() {
+            return;
+        }
+        state.mode = mode;
+        state.next_mode = None;
+        state.postfx.fade_in(FADE_TICKS);
+    }
     match state.mode {
         Mode::TitleScreen => {
-            state
-                .audio
-                .play(SoundID(0), false, None, AlreadyPlayingAction::Nothing);
+            state.audio.play(
+                state.sounds.title,
+                false,
+                None,
+                AlreadyPlayingAction::Nothing,
+            );
 
             if input.key_held(VirtualKeyCode::Return) {
-                state.audio.stop(SoundID(0), None);
-                state.mode = Mode::GamePlay
+                state.audio.stop(state.sounds.title, None);
+                change_mode(state, Mode::GamePlay);
             }
         }
         Mode::GamePlay => {
-            state
-                .audio
-                .play(SoundID(1), true, Some(0.0), AlreadyPlayingAction::Nothing);
-
-            if !&state.sprites[0].on_screen(state.camera_position, HEIGHT, WIDTH) {
-                state.audio.stop(SoundID(1), None);
-                state
-                    .audio
-                    .play(SoundID(3), true, Some(0.0), AlreadyPlayingAction::Nothing);
-                state.mode = Mode::EndGame;
+            state.audio.play(
+                state.sounds.gameplay,
+                true,
+                Some(0.0),
+                AlreadyPlayingAction::Nothing,
+            );
+
+            if !&state.sprites[0].on_screen(state.camera.position(), HEIGHT, WIDTH) {
+                state.audio.stop(state.sounds.gameplay, None);
+                state.audio.play(
+                    state.sounds.end,
+                    true,
+                    Some(0.0),
+                    AlreadyPlayingAction::Nothing,
+                );
+                change_mode(state, Mode::EndGame);
             };
 
             tile_collision(state);
@@ -598,10 +843,11 @@ fn update_game(
 
             state.sprites[0].tick_forward();
 
-            scroll_camera(state);
+            state.camera.scroll = Vec2f(state.camera_speed, 0.0);
+            state.camera.update(None);
         }
         Mode::EndGame => {
-            state.camera_position = Vec2f(0.0, 0.0);
+            state.camera.set_position(Vec2f(0.0, 0.0));
             state.camera_speed = START_SPEED;
             state.level = 0;
             state.sprites[0].vx = SPRITE_INITIAL_VX;
@@ -609,17 +855,6 @@ fn update_game(
             state.sprites[0].rect.x = SPRITE_INITIAL_X;
             state.sprites[0].rect.y = SPRITE_INITIAL_Y;
 
-            let mut bg_tilemaps = vec![];
-            for (i, map) in state.bg_tilemaps.iter().enumerate() {
-                let new = Tilemap {
-                    position: Vec2f((i * WIDTH) as f32, 0.0),
-                    dims: map.dims,
-                    tileset: Rc::clone(&map.tileset),
-                    map: map.map.clone(),
-                };
-                bg_tilemaps.push(Rc::new(new));
-            }
-
             let mut obstacle_tilemaps = vec![];
             for (i, map) in state.obstacle_tilemaps.iter().enumerate() {
                 let new = Tilemap::new(
@@ -630,16 +865,16 @@ fn update_game(
                     map.dims,
                     &Rc::clone(&map.tileset),
                     Tilemap::generate_rand_map_2(START_P, map.dims, TileID(8), TileID(7)),
-                );
+                )
+                .expect("Meteor maps only use tiles from the tileset");
                 obstacle_tilemaps.push(Rc::new(new));
             }
 
-            state.bg_tilemaps = bg_tilemaps;
             state.obstacle_tilemaps = obstacle_tilemaps;
 
             if input.key_held(VirtualKeyCode::Return) {
-                state.audio.stop(SoundID(3), None);
-                state.mode = Mode::GamePlay;
+                state.audio.stop(state.sounds.end, None);
+                change_mode(state, Mode::GamePlay);
             }
         }
     }
@@ -655,103 +890,68 @@ fn new_level(state: &mut GameState) {
         state.font.clone(),
         &levelup,
         Vec2f(
-            state.camera_position.0 + (WIDTH / 2) as f32,
+            state.camera.position().0 + (WIDTH / 2) as f32,
             (HEIGHT / 2) as f32,
         ),
     ));
 }
 
-fn update_tilemaps(
-    camera_position: Vec2f,
-    tilemaps: &mut Vec<Rc<Tilemap>>,
-    is_obstacle: bool,
-    level: usize,
-) {
+fn update_tilemaps(camera_position: Vec2f, tilemaps: &mut Vec<Rc<Tilemap>>, level: usize) {
     let p = START_P - 0.03 * level as f32;
     let first = &tilemaps[0];
     if first.position.0 as usize + first.size().0 * TILE_SZ < camera_position.0 as usize {
         let last = tilemaps.last().unwrap();
-        let new: Tilemap;
-        if is_obstacle {
-            new = Tilemap::new(
-                Vec2f(last.position.0 + last.size().0 as f32 * TILE_SZ as f32, 0.0),
-                first.dims,
-                &Rc::clone(&first.tileset),
-                Tilemap::generate_rand_map_2(p, first.dims, TileID(8), TileID(7)),
-            );
-        } else {
-            new = Tilemap {
-                position: Vec2f(last.position.0 + last.size().0 as f32 * TILE_SZ as f32, 0.0),
-                dims: first.dims,
-                tileset: Rc::clone(&first.tileset),
-                map: first.map.clone(),
-            };
-        }
+        let new = Tilemap::new(
+            Vec2f(last.position.0 + last.size().0 as f32 * TILE_SZ as f32, 0.0),
+            first.dims,
+            &Rc::clone(&first.tileset),
+            Tilemap::generate_rand_map_2(p, first.dims, TileID(8), TileID(7)),
+        )
+        .expect("Meteor maps only use tiles from the tileset");
         tilemaps.remove(0);
         tilemaps.push(Rc::new(new));
     }
 }
 
-fn tile_collision(state: &mut GameState) {
-    let x = state.sprites[0].rect.x;
-    let y = state.sprites[0].rect.y;
+// Where on the player we check for meteors
+fn collision_points(s: &Sprite) -> Vec<Vec2f> {
+    let x = s.rect.x;
+    let y = s.rect.y;
 
     let tl = Vec2f(x + 12.0, y + 12.0);
-    let tr = Vec2f(x + state.sprites[0].rect.w as f32 - 12.0, y + 12.0);
-    let bl = Vec2f(x + 12.0, y + state.sprites[0].rect.h as f32);
-    let br = Vec2f(
-        x + state.sprites[0].rect.w as f32 - 12.0,
-        y + state.sprites[0].rect.h as f32,
-    );
-    let posns = vec![tl, tr, bl, br];
+    let tr = Vec2f(x + s.rect.w as f32 - 12.0, y + 12.0);
+    let bl = Vec2f(x + 12.0, y + s.rect.h as f32);
+    let br = Vec2f(x + s.rect.w as f32 - 12.0, y + s.rect.h as f32);
+    vec![tl, tr, bl, br]
+}
+
+fn tile_collision(state: &mut GameState) {
+    let posns = collision_points(&state.sprites[0]);
 
     for posn in posns {
         let map_idx = tile_map_at(posn, &state.obstacle_tilemaps);
         if let Some(i) = map_idx {
             if let Some(t) = state.obstacle_tilemaps[i].tile_at(posn) {
                 if t.solid {
-                    state
-                        .audio
-                        .play(SoundID(2), false, None, AlreadyPlayingAction::Nothing);
-                    state
-                        .audio
-                        .play(SoundID(3), true, Some(0.0), AlreadyPlayingAction::Nothing);
-                    state.mode = Mode::EndGame
+                    state.audio.play(
+                        state.sounds.collision,
+                        false,
+                        None,
+                        AlreadyPlayingAction::Nothing,
+                    );
+                    state.audio.play(
+                        state.sounds.end,
+                        true,
+                        Some(0.0),
+                        AlreadyPlayingAction::Nothing,
+                    );
+                    change_mode(state, Mode::EndGame);
                 }
             }
         }
     }
 }
 
-fn scroll_camera(state: &mut GameState) {
-    state.camera_position.0 += state.camera_speed;
-}
-
-fn update_camera(state: &mut GameState) {
-    // right side
-    if state.sprites[0].rect.x + PLAYER_WIDTH as f32 >= state.camera_position.0 + WIDTH as f32 - 5.0
-    {
-        state.camera_position.0 += state.camera_speed;
-    }
-
-    // left side
-    if state.sprites[0].rect.x <= state.camera_position.0 + 5.0 {
-        state.camera_position.0 -= state.camera_speed;
-    }
-
-    // top
-    if state.sprites[0].rect.y <= state.camera_position.1 + 5.0 {
-        state.camera_position.1 -= state.camera_speed;
-    }
-
-    // bottom
-    if state.sprites[0].rect.y + PLAYER_HEIGHT as f32
-        >= state.camera_position.1 + HEIGHT as f32 - 5.0
-    {
-        state.camera_position.1 += state.camera_speed;
-    }
-}
-
 fn tile_map_at(posn: Vec2f, tilemaps: &Vec<Rc<Tilemap>>) -> Option<usize> {
     for (i, map) in tilemaps.iter().enumerate() {
         let is_on_x = posn.0 >= map.position.0
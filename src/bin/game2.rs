@@ -3,8 +3,8 @@ use anim2d::scores::Score;
 use anim2d::scores::Scores;
 use kira::manager::AudioManager;
 use kira::manager::AudioManagerSettings;
-use kira::sound::SoundSettings;
 use pixels::{Pixels, SurfaceTexture};
+use rand::Rng;
 use std::cell::RefCell;
 use std::path::Path;
 use std::rc::Rc;
@@ -12,12 +12,20 @@ use std::time::Instant;
 use winit::dpi::LogicalSize;
 use winit::event::{Event, VirtualKeyCode};
 use winit::event_loop::{ControlFlow, EventLoop};
-use winit::window::WindowBuilder;
+use winit::window::{Fullscreen, WindowBuilder};
 use winit_input_helper::WinitInputHelper;
 
 use anim2d::animation::*;
+use anim2d::assets::Assets;
 use anim2d::background::*;
-use anim2d::screen::Screen;
+use anim2d::camera::Camera;
+use anim2d::capture::{self, RecordFormat, Recorder};
+use anim2d::debug::DebugOverlay;
+use anim2d::dirty::StaticLayer;
+use anim2d::lighting::{AmbientRegion, Light, LightMap};
+use anim2d::nineslice::{DrawNineSliceExt, NineSlice};
+use anim2d::postfx::PostFx;
+use anim2d::screen::{Blend, BlendMode, Screen};
 use anim2d::sprite::*;
 use anim2d::text::*;
 use anim2d::texture::Texture;
@@ -25,6 +33,7 @@ use anim2d::tiles::Tilemap;
 use anim2d::tiles::*;
 use anim2d::types::Vec2f;
 use anim2d::types::*;
+use anim2d::watch::Watcher;
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 enum Mode {
@@ -33,26 +42,45 @@ enum Mode {
     EndGame,
 }
 
+// Which loaded sound is which
+struct Sounds {
+    title: SoundID,
+    gameplay: SoundID,
+    end: SoundID,
+}
+
 // Now this main module is just for the run-loop and rules processing.
 struct GameState {
     // What data do we need for this game?  Wall positions?
     // Colliders?  Sprites and stuff?
-    animations: Vec<Rc<Animation>>,
-    textures: Vec<Rc<Texture>>,
+    assets: Assets,
+    sounds: Sounds,
     sprites: Vec<Sprite>,
     backgrounds: Vec<Background>,
     curr_location: usize,
     bg_tilemaps: Vec<Rc<RefCell<Tilemap>>>,
-    camera_position: Vec2f,
+    camera: Camera,
     camera_speed: f32,
     mode: Mode,
     font: Rc<Font>,
+    panel: NineSlice,
+    // What shows through the water in the tunnels
+    backdrop: Parallax,
     level: usize,
     text: Vec<Text>,
     scores: Scores,
     start: Instant,
     og_tilemaps: Vec<Tilemap>,
     audio: Audio,
+    lights: LightMap,
+    debug: DebugOverlay,
+    // Where the mouse is in the framebuffer, if it's over the game
+    mouse: Option<Vec2f>,
+    postfx: PostFx,
+    // Where we're going once the fade out finishes
+    next_mode: Option<Mode>,
+    // Backgrounds and tiles, kept around so frames only redraw what moved
+    layer: StaticLayer,
 }
 
 // seconds per frame
@@ -61,6 +89,13 @@ const DT: f64 = 1.0 / 60.0;
 const WIDTH: usize = 512;
 const HEIGHT: usize = 1024;
 const DEPTH: usize = 4;
+// How long switching modes takes to fade out, and then back in
+const FADE_TICKS: usize = 20;
+// Where screenshots and recordings go
+const CAPTURE_DIR: &str = "captures";
+// Record every third frame, i.e. 20 frames a second at 60Hz
+const RECORD_EVERY: usize = 3;
+const SCORES_PATH: &str = "data/scores.json";
 const PLAYER_WIDTH: u16 = 64;
 const PLAYER_HEIGHT: u16 = 64;
 const START_SPEED: f32 = 0.5;
@@ -68,6 +103,14 @@ const SPRITE_INITIAL_X: f32 = 60.0;
 const SPRITE_INITIAL_Y: f32 = 112.0;
 
 fn main() {
+    // Tell whoever's running it what went wrong, e.g. which file is missing
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run() -> anim2d::Result<()> {
     let event_loop = EventLoop::new();
     let mut input = WinitInputHelper::new();
     let window = {
@@ -76,7 +119,6 @@ fn main() {
             .with_title("Dig to Free the Beached Whale")
             .with_inner_size(size)
             .with_min_inner_size(size)
-            .with_resizable(false)
             .build(&event_loop)
             .unwrap()
     };
@@ -85,19 +127,11 @@ fn main() {
         let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
         Pixels::new(WIDTH as u32, HEIGHT as u32, surface_texture).unwrap()
     };
-    let scuba = Rc::new(Texture::with_file(Path::new("content/scubasprite.png")));
-    let tex = Rc::new(Texture::with_file(Path::new("content/tiles_dig.png")));
-    let start = Background::new(
-        &Rc::new(Texture::with_file(Path::new("content/startscreen.png"))),
-        WIDTH,
-        HEIGHT,
-    );
-    let end = Background::new(
-        &Rc::new(Texture::with_file(Path::new("content/endscreen.png"))),
-        WIDTH,
-        HEIGHT,
-    );
-    let tileset = Rc::new(Tileset::new(
+    let mut assets = Assets::new("content");
+    let scuba = assets.sheet("scubasprite.json")?;
+    let tileset = assets.tileset(
+        "dig",
+        "tiles_dig.png",
         vec![
             Tile {
                 solid: false,
@@ -130,8 +164,8 @@ fn main() {
                 destructible: false,
             }, // walls
         ],
-        &tex,
-    ));
+    )?;
+    let tileset = Rc::clone(assets.get(tileset));
     let map1 = Tilemap::new(
         Vec2f(0.0, 0.0),
         (16, 32),
@@ -156,7 +190,7 @@ fn main() {
             5, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 5, 5, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0,
             0, 0, 5, 5, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5,
         ],
-    );
+    )?;
 
     let map2 = Tilemap::new(
         Vec2f(0.0, 1024.0),
@@ -182,7 +216,7 @@ fn main() {
             5, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 5, 5, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0,
             0, 0, 5, 5, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5,
         ],
-    );
+    )?;
 
     let map3 = Tilemap::new(
         Vec2f(0.0, 2048.0),
@@ -208,7 +242,7 @@ fn main() {
             5, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 5, 5, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0,
             0, 0, 5, 5, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 5,
         ],
-    );
+    )?;
     let map4 = Tilemap::new(
         Vec2f(0.0, 3072.0),
         (16, 32),
@@ -233,17 +267,17 @@ fn main() {
             5, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 1, 5, 5, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0,
             0, 0, 5, 5, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 5,
         ],
-    );
+    )?;
 
-    let swim_frames = Rectf::create_frames(0, 4, PLAYER_WIDTH, PLAYER_HEIGHT);
-    let swim_timing = vec![6, 6, 6, 6];
-
-    let swim = Rc::new(Animation::new(swim_frames, swim_timing, true));
-    let animations = vec![swim];
-    let animations_clone = animations.clone();
+    let swim = assets
+        .find::<Animation>("swim")
+        .ok_or_else(|| anim2d::Error::Sheet {
+            path: assets.resolve("scubasprite.json"),
+            why: "there's no swim animation".to_string(),
+        })?;
     let player = Sprite::new(
-        &scuba,
-        &animations_clone[0],
+        assets.get(scuba),
+        assets.get(swim),
         AnimationState::new(),
         Rectf {
             x: 60.0,
@@ -256,22 +290,58 @@ fn main() {
     );
     let sprites = vec![player];
 
-    let start = Background::new(
-        &Rc::new(Texture::with_file(Path::new("content/startdig.png"))),
-        WIDTH,
-        HEIGHT,
-    );
-    let end = Background::new(
-        &Rc::new(Texture::with_file(Path::new("content/enddig.png"))),
-        WIDTH,
-        HEIGHT,
-    );
+    let title_image = assets.texture("startdig.png")?;
+    let end_image = assets.texture("enddig.png")?;
+    let mut start = Background::new(assets.get(title_image), WIDTH, HEIGHT);
+    let mut end = Background::new(assets.get(end_image), WIDTH, HEIGHT);
+    // The ship's name glows on and off
+    for bg in [&mut start, &mut end] {
+        bg.cycle_colors(ColorCycle::new(
+            vec![
+                Rgba(254, 249, 218, 255),
+                Rgba(255, 255, 240, 255),
+                Rgba(254, 249, 218, 255),
+                Rgba(222, 214, 176, 255),
+            ],
+            12,
+        ));
+    }
 
-    let font = Rc::new(Font {
-        image: Rc::new(Texture::with_file(Path::new("content/ascii.png"))),
-    });
+    let font = assets.font("ascii.png")?;
+    let font = Rc::clone(assets.get(font));
+
+    // UI panels: see-through so the title art shows behind them, with a
+    // brighter rim.  Drawn once here and nine-sliced to whatever size.
+    let mut panel_image = Texture::blank(24, 24);
+    let panel_frame = Rect {
+        x: 0,
+        y: 0,
+        w: 24,
+        h: 24,
+    };
+    {
+        let mut screen = panel_image.screen(Vec2f(0.0, 0.0));
+        screen.fill_rounded_rect(panel_frame, 8.0, Rgba(115, 115, 115, 160));
+        screen.rounded_rect(panel_frame, 8.0, 2.0, Rgba(190, 190, 190, 200));
+    }
+    let panel = NineSlice::new(&Rc::new(panel_image), panel_frame, (8, 8, 8, 8));
+
+    // Layers of earth behind the tunnels, slower the further back they are
+    let mut backdrop = Parallax::new();
+    for (bg, factor) in [
+        (
+            dirt_layer(Some(Rgba(48, 32, 20, 255)), Rgba(32, 20, 12, 255), 120),
+            0.2,
+        ),
+        (dirt_layer(None, Rgba(88, 62, 38, 255), 50), 0.5),
+    ] {
+        backdrop.push(ParallaxLayer::new(bg, Vec2f(0.0, factor)));
+    }
 
-    let mut scores = Scores::new("data/scores.json");
+    let mut scores = Scores::new(SCORES_PATH).unwrap_or_else(|e| {
+        eprintln!("{}; starting without high scores", e);
+        Scores::default()
+    });
     scores.sort();
 
     let original_map1 = map1.clone();
@@ -279,36 +349,61 @@ fn main() {
     let original_map3 = map3.clone();
     let original_map4 = map4.clone();
 
-    let mut audio_manager = AudioManager::new(AudioManagerSettings::default()).unwrap();
-    let startscreen = audio_manager
-        .load_sound(
-            "content/digstartscreen.mp3",
-            SoundSettings::default(), // SoundSettings::new().semantic_duration(Tempo(128.0).beats_to_seconds(8.0)),
-        )
-        .unwrap();
-    let gameplay = audio_manager
-        .load_sound(
-            "content/diggameplay.mp3",
-            SoundSettings::default(), // SoundSettings::new().semantic_duration(Tempo(128.0).beats_to_seconds(8.0)),
-        )
-        .unwrap();
-    let endscreen = audio_manager
-        .load_sound(
-            "content/digendscreen.mp3",
-            SoundSettings::default(), // SoundSettings::new().semantic_duration(Tempo(128.0).beats_to_seconds(8.0)),
-        )
-        .unwrap();
-    let sound_handles = vec![startscreen, gameplay, endscreen];
-    let audio = Audio::new(audio_manager, sound_handles);
+    let mut audio = Audio::new(AudioManager::new(AudioManagerSettings::default())?, vec![]);
+    let sounds = Sounds {
+        title: assets.sound(&mut audio, "digstartscreen.mp3"),
+        gameplay: assets.sound(&mut audio, "diggameplay.mp3"),
+        end: assets.sound(&mut audio, "digendscreen.mp3"),
+    };
+    for path in assets.missing() {
+        eprintln!("Couldn't load {}, carrying on without it", path.display());
+    }
 
     // Track beginning of play
     let start_time = Instant::now();
 
+    // Only follow the diver downwards, once they're in the top third of the screen
+    let mut camera = Camera::new(Vec2f(0.0, 0.0), WIDTH, HEIGHT);
+    camera.dead_zone = Rect {
+        x: 0,
+        y: -(HEIGHT as i32),
+        w: WIDTH as u16,
+        h: (2 * HEIGHT - 700) as u16,
+    };
+    camera.max_speed = Some(5.0);
+
+    // Daylight at the surface, getting darker the deeper the diver goes,
+    // with a lamp to see by
+    let mut lights = LightMap::new(Rgba(255, 255, 255, 255));
+    lights.regions.push(AmbientRegion {
+        area: Rect {
+            x: 0,
+            y: 0,
+            w: WIDTH as u16,
+            h: 1024,
+        },
+        top: Rgba(255, 255, 255, 255),
+        bottom: Rgba(90, 90, 110, 255),
+    });
+    lights.regions.push(AmbientRegion {
+        area: Rect {
+            x: 0,
+            y: 1024,
+            w: WIDTH as u16,
+            h: 3072,
+        },
+        top: Rgba(90, 90, 110, 255),
+        bottom: Rgba(10, 10, 25, 255),
+    });
+    let mut lamp = Light::new(Vec2f(0.0, 0.0), 260.0, Rgba(255, 225, 170, 255));
+    lamp.falloff = 1.5;
+    lights.lights.push(lamp);
+
     let mut state = GameState {
         // initial game state...
-        animations,
+        assets,
+        sounds,
         sprites,
-        textures: vec![scuba],
         backgrounds: vec![start, end],
         curr_location: 0,
         bg_tilemaps: vec![
@@ -317,16 +412,24 @@ fn main() {
             Rc::new(RefCell::new(map3)),
             Rc::new(RefCell::new(map4)),
         ],
-        camera_position: Vec2f(0.0, 0.0),
+        camera,
         camera_speed: 0.0,
         mode: Mode::TitleScreen,
         font,
+        panel,
+        backdrop,
         level: 0,
         text: vec![],
         scores,
         start: start_time,
         og_tilemaps: vec![original_map1, original_map2, original_map3, original_map4],
         audio,
+        lights,
+        debug: DebugOverlay::new(),
+        mouse: None,
+        postfx: PostFx::new(),
+        next_mode: None,
+        layer: StaticLayer::new(),
     };
 
     // How many frames have we simulated?
@@ -335,22 +438,51 @@ fn main() {
     let mut available_time = 0.0;
     // Track end of the last frame
     let mut since = Instant::now();
+    // Screenshots are taken when the next frame is drawn
+    let mut take_screenshot = false;
+    let mut recorder: Option<Recorder> = None;
+    // Debug builds pick up art, sprite sheets and scores that are edited
+    // while the game runs (the maps are still written out in code)
+    let mut watcher = if cfg!(debug_assertions) {
+        Some(Watcher::new(&["content", "data"]))
+    } else {
+        None
+    };
     event_loop.run(move |event, _, control_flow| {
         // Draw the current frame
         if let Event::RedrawRequested(_) = event {
+            state.debug.frame();
             let mut screen = Screen::wrap(
                 pixels.get_frame(),
                 WIDTH,
                 HEIGHT,
                 DEPTH,
-                state.camera_position,
+                state.camera.screen_position(),
             );
-            screen.clear(Rgba(0, 0, 0, 0));
 
             draw_game(&mut state, &mut screen);
+            state.postfx.apply(&mut screen);
+            // Nothing else draws this frame; remember what to clean up next time
+            state.layer.end(&mut screen);
+            state.debug.redrawn(state.layer.redrawn(), WIDTH * HEIGHT);
+
+            if take_screenshot {
+                take_screenshot = false;
+                match capture::screenshot(&screen, Path::new(CAPTURE_DIR)) {
+                    Ok(path) => println!("Saved {}", path.display()),
+                    Err(e) => eprintln!("Couldn't save screenshot: {}", e),
+                }
+            }
+            if let Some(r) = recorder.as_mut() {
+                r.capture(&screen);
+            }
 
             // Flip buffers
             if pixels.render().is_err() {
+                // Exiting doesn't wait for the recording thread
+                if let Some(r) = recorder.take() {
+                    finish_recording(r);
+                }
                 *control_flow = ControlFlow::Exit;
                 return;
             }
@@ -363,13 +495,50 @@ fn main() {
         if input.update(event) {
             // Close events
             if input.key_pressed(VirtualKeyCode::Escape) || input.quit() {
+                // Exiting doesn't wait for the recording thread
+                if let Some(r) = recorder.take() {
+                    finish_recording(r);
+                }
                 *control_flow = ControlFlow::Exit;
                 return;
             }
-            // Resize the window if needed
+            // Show or hide colliders and frame stats
+            if input.key_pressed(VirtualKeyCode::F3) {
+                state.debug.toggle();
+                // The tile grid is only drawn where the static layer redraws
+                state.layer.invalidate();
+            }
+            // F12 saves a screenshot; F11 records a gif and F10 a PNG sequence
+            if input.key_pressed(VirtualKeyCode::F12) {
+                take_screenshot = true;
+            }
+            if input.key_pressed(VirtualKeyCode::F11) {
+                toggle_recording(&mut recorder, RecordFormat::Gif);
+            }
+            if input.key_pressed(VirtualKeyCode::F10) {
+                toggle_recording(&mut recorder, RecordFormat::PngSequence);
+            }
+            // F9 switches between a window and fullscreen
+            if input.key_pressed(VirtualKeyCode::F9) {
+                if window.fullscreen().is_some() {
+                    window.set_fullscreen(None);
+                } else {
+                    window.set_fullscreen(Some(Fullscreen::Borderless(window.current_monitor())));
+                }
+            }
+            // Resize the window if needed; pixels scales the framebuffer up by
+            // whole numbers and centers it
             if let Some(size) = input.window_resized() {
                 pixels.resize(size.width, size.height);
             }
+            // Through the same scaling pixels draws with, so they line up
+            state.mouse = input
+                .mouse()
+                .and_then(|m| pixels.window_pos_to_pixel(m).ok())
+                .map(|(x, y)| Vec2f(x as f32, y as f32));
+        }
+        if let Some(w) = watcher.as_mut() {
+            reload_changed(&mut state, w);
         }
         // And the simulation "consumes" it
         while available_time >= DT {
@@ -389,24 +558,20 @@ fn main() {
 }
 
 fn draw_scores(state: &mut GameState, screen: &mut Screen) {
-    // scores box
-    let box_color = Rgba(115, 115, 115, 0);
     let r = Rect {
         x: 120,
         y: 475,
         w: 270,
         h: 275,
     };
-    screen.rect(r, box_color);
-    screen.line(
-        Vec2f(r.x as f32, r.y as f32 - 5.0),
-        Vec2f(r.x as f32 + r.w as f32, r.y as f32 - 5.0),
-        box_color,
-    );
-    screen.line(
-        Vec2f(r.x as f32, (r.y + r.h as i32) as f32 + 5.0),
-        Vec2f(r.x as f32 + r.w as f32, (r.y + r.h as i32) as f32 + 5.0),
-        box_color,
+    // scores box, with a little room above and below the scores
+    screen.draw_nine_slice(
+        &state.panel,
+        Rect {
+            y: r.y - 5,
+            h: r.h + 10,
+            ..r
+        },
     );
 
     // scores text
@@ -417,6 +582,8 @@ fn draw_scores(state: &mut GameState, screen: &mut Screen) {
     );
     screen.draw_text(&mut scores_text);
 
+    // lots of scores shouldn't spill out of the box
+    screen.push_clip(r);
     for (i, score) in state.scores.scores.iter().enumerate() {
         let mut score_text = Text::new(
             state.font.clone(),
@@ -425,10 +592,39 @@ fn draw_scores(state: &mut GameState, screen: &mut Screen) {
         );
         screen.draw_text(&mut score_text);
     }
+    screen.pop();
 }
 
-fn draw_game(state: &mut GameState, screen: &mut Screen) {
-    // Call screen's drawing methods to render the game state
+fn toggle_recording(recorder: &mut Option<Recorder>, format: RecordFormat) {
+    match recorder.take() {
+        Some(r) => finish_recording(r),
+        None => match Recorder::start(Path::new(CAPTURE_DIR), format, RECORD_EVERY) {
+            Ok(r) => {
+                println!("Recording to {}", r.path().display());
+                *recorder = Some(r);
+            }
+            Err(e) => eprintln!("Couldn't start recording: {}", e),
+        },
+    }
+}
+
+fn finish_recording(r: Recorder) {
+    let path = r.path().to_path_buf();
+    let dropped = r.dropped();
+    match r.finish() {
+        Ok(n) if dropped > 0 => println!(
+            "Saved {} frames to {} ({} dropped to keep up)",
+            n,
+            path.display(),
+            dropped
+        ),
+        Ok(n) => println!("Saved {} frames to {}", n, path.display()),
+        Err(e) => eprintln!("Couldn't save recording: {}", e),
+    }
+}
+
+// Everything that stays put until something invalidates state.layer
+fn draw_static(state: &mut GameState, screen: &mut Screen) {
     screen.clear(Rgba(80, 80, 80, 255));
 
     match state.mode {
@@ -446,51 +642,15 @@ fn draw_game(state: &mut GameState, screen: &mut Screen) {
             screen.draw_text(&mut start_text);
         }
         Mode::GamePlay => {
-            let screen_corners = vec![
-                Vec2f(state.camera_position.0, state.camera_position.1),
-                Vec2f(
-                    state.camera_position.0 + WIDTH as f32,
-                    state.camera_position.1,
-                ),
-                Vec2f(
-                    state.camera_position.0,
-                    state.camera_position.1 + HEIGHT as f32,
-                ),
-                Vec2f(
-                    state.camera_position.0 + WIDTH as f32,
-                    state.camera_position.1 + HEIGHT as f32,
-                ),
-            ];
-            let mut draw_bgmaps = vec![];
-            for posn in screen_corners {
-                if let Some(i) = tile_map_at(posn, &state.bg_tilemaps) {
-                    let map = &state.bg_tilemaps[i];
-                    if !draw_bgmaps.contains(&map) {
-                        draw_bgmaps.push(map);
-                    }
-                }
-            }
-
-            for map in draw_bgmaps {
+            // Dug out tiles turn to water, which the backdrop shows through
+            screen.draw_parallax(&state.backdrop);
+            let mut water = Blend::new(BlendMode::Over);
+            water.opacity = 0.6;
+            for map in visible_tilemaps(state) {
                 let map = map.borrow();
-                map.draw(screen);
-            }
-
-            for s in state.sprites.iter() {
-                screen.draw_sprite(s);
+                map.draw_matching(screen, Blend::new(BlendMode::Over), |id| id != TileID(4));
+                map.draw_matching(screen, water, |id| id == TileID(4));
             }
-
-            // start text
-            let mut time = Text::new(
-                state.font.clone(),
-                format!("TIME: {}", state.start.elapsed().as_secs()).as_str(),
-                Vec2f(
-                    40.0 + state.camera_position.0,
-                    60.0 + state.camera_position.1,
-                ),
-            );
-
-            screen.draw_text(&mut time);
         }
         Mode::EndGame => {
             screen.draw_background(&state.backgrounds[1]);
@@ -510,29 +670,222 @@ fn draw_game(state: &mut GameState, screen: &mut Screen) {
     }
 }
 
+// A screen-sized tile of scattered pebbles, over solid ground or over nothing
+fn dirt_layer(ground: Option<Rgba>, pebbles: Rgba, count: usize) -> Background {
+    let mut image = Texture::blank(WIDTH, HEIGHT);
+    {
+        let mut screen = image.screen(Vec2f(0.0, 0.0));
+        if let Some(col) = ground {
+            screen.clear(col);
+        }
+        let mut rng = rand::thread_rng();
+        for _ in 0..count {
+            let center = Vec2f(
+                rng.gen_range(8.0..(WIDTH - 8) as f32),
+                rng.gen_range(8.0..(HEIGHT - 8) as f32),
+            );
+            let radii = Vec2f(rng.gen_range(2.0..7.0), rng.gen_range(1.5..5.0));
+            screen.fill_ellipse(center, radii, pebbles);
+        }
+    }
+    Background::new(&Rc::new(image), WIDTH, HEIGHT)
+}
+
+// The tilemaps under the camera's corners
+fn visible_tilemaps(state: &GameState) -> Vec<Rc<RefCell<Tilemap>>> {
+    let camera_position = state.camera.position();
+    let screen_corners = vec![
+        Vec2f(camera_position.0, camera_position.1),
+        Vec2f(camera_position.0 + WIDTH as f32, camera_position.1),
+        Vec2f(camera_position.0, camera_position.1 + HEIGHT as f32),
+        Vec2f(
+            camera_position.0 + WIDTH as f32,
+            camera_position.1 + HEIGHT as f32,
+        ),
+    ];
+    let mut maps: Vec<Rc<RefCell<Tilemap>>> = vec![];
+    for posn in screen_corners {
+        if let Some(i) = tile_map_at(posn, &state.bg_tilemaps) {
+            let map = &state.bg_tilemaps[i];
+            if !maps.iter().any(|m| Rc::ptr_eq(m, map)) {
+                maps.push(Rc::clone(map));
+            }
+        }
+    }
+    maps
+}
+
+fn reload_changed(state: &mut GameState, watcher: &mut Watcher) {
+    for path in watcher.poll() {
+        if path == Path::new(SCORES_PATH) {
+            // Saving a score lands here too, which just reads it back
+            match Scores::load(SCORES_PATH) {
+                Ok(mut scores) => {
+                    scores.sort();
+                    state.scores = scores;
+                }
+                Err(e) => eprintln!("{}", e),
+            }
+            continue;
+        }
+        match state.assets.reload(&path) {
+            Ok(true) => {
+                println!("Reloaded {}", path.display());
+                // The cached tiles were drawn with the old pixels
+                state.layer.invalidate();
+                // And the swim cycle, if it was the diver's sheet
+                if let Some(swim) = state.assets.find::<Animation>("swim") {
+                    state.sprites[0].animation = Rc::clone(state.assets.get(swim));
+                }
+            }
+            Ok(false) => {}
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+}
+
+fn draw_game(state: &mut GameState, screen: &mut Screen) {
+    // Call screen's drawing methods to render the game state.
+    // The static layer puts back whatever moved last frame; everything after
+    // it gets drawn fresh each frame.
+    let mut layer = std::mem::take(&mut state.layer);
+    if state.mode == Mode::GamePlay {
+        // The cache is unlit, and only what it puts back gets lit again, so
+        // that has to cover the sprites and wherever the light has changed
+        for s in state.sprites.iter() {
+            layer.refresh_rect(Rect {
+                x: s.rect.x.floor() as i32,
+                y: s.rect.y.floor() as i32,
+                w: s.rect.w + 1,
+                h: s.rect.h + 1,
+            });
+        }
+        for r in state.lights.changed() {
+            layer.refresh_rect(r);
+        }
+    }
+    layer.begin(screen, |s| draw_static(state, s));
+    state.layer = layer;
+
+    if state.mode == Mode::GamePlay {
+        let draw_bgmaps = visible_tilemaps(state);
+
+        for s in state.sprites.iter() {
+            screen.draw_sprite(s);
+        }
+
+        // Darken everything drawn so far; the HUD goes on top, unlit
+        let blockers: Vec<_> = draw_bgmaps.iter().map(|m| m.borrow()).collect();
+        let blockers: Vec<&Tilemap> = blockers.iter().map(|m| &**m).collect();
+        state
+            .lights
+            .apply_to(screen, state.layer.restored(), &blockers);
+
+        // start text
+        let mut time = Text::new(
+            state.font.clone(),
+            format!("TIME: {}", state.start.elapsed().as_secs()).as_str(),
+            Vec2f(
+                40.0 + state.camera.position().0,
+                60.0 + state.camera.position().1,
+            ),
+        );
+
+        screen.draw_text(&mut time);
+
+        // The tile grid doesn't move, so like the lighting it only needs
+        // doing where the layer put the world back.  Tracking it would have
+        // it put back (and drawn again) every frame.
+        let tracked = screen.untrack();
+        let Vec2f(cx, cy) = screen.position();
+        for r in state.layer.restored().rects() {
+            screen.push_clip(Rect {
+                x: r.x + cx as i32,
+                y: r.y + cy as i32,
+                ..r
+            });
+            for map in draw_bgmaps.iter() {
+                state.debug.draw_tilemap(screen, &map.borrow());
+            }
+            screen.pop();
+        }
+        if let Some(tracked) = tracked {
+            screen.track(tracked);
+        }
+        state.debug.draw_sprites(screen, &state.sprites);
+        state
+            .debug
+            .draw_points(screen, &collision_points(&state.sprites[0]));
+        if let Some(Vec2f(x, y)) = state.mouse {
+            let Vec2f(cx, cy) = screen.position();
+            state.debug.draw_points(screen, &[Vec2f(x + cx, y + cy)]);
+        }
+    }
+    state.debug.draw_stats(screen, &state.font);
+}
+
+// Fade out, switch to mode, and fade back in
+fn change_mode(state: &mut GameState, mode: Mode) {
+    if state.next_mode.is_none() {
+        state.next_mode = Some(mode);
+        state.postfx.fade_out(Rgba(0, 0, 0, 255), FADE_TICKS);
+    }
+}
+
 fn update_game(state: &mut GameState, input: &WinitInputHelper) {
+    state.postfx.update();
+    // Backgrounds run their own animations
+    let shown = match state.mode {
+        Mode::TitleScreen => Some(0),
+        Mode::EndGame => Some(1),
+        Mode::GamePlay => None,
+    };
+    for (i, bg) in state.backgrounds.iter_mut().enumerate() {
+        if bg.update() && shown == Some(i) {
+            state.layer.invalidate();
+        }
+    }
+    // Hold everything still while fading out, then switch over
+    if let Some(mode) = state.next_mode {
+        if state.postfx.fading() {
+            return;
+        }
+        state.mode = mode;
+        state.next_mode = None;
+        state.layer.invalidate();
+        state.postfx.fade_in(FADE_TICKS);
+    }
     match state.mode {
         Mode::TitleScreen => {
-            state
-                .audio
-                .play(SoundID(0), false, None, AlreadyPlayingAction::Nothing);
+            state.audio.play(
+                state.sounds.title,
+                false,
+                None,
+                AlreadyPlayingAction::Nothing,
+            );
 
             if input.key_held(VirtualKeyCode::Return) {
                 state.start = Instant::now();
-                state.audio.stop(SoundID(0), None);
-                state
-                    .audio
-                    .play(SoundID(1), true, Some(0.0), AlreadyPlayingAction::Nothing);
-                state.mode = Mode::GamePlay;
+                state.audio.stop(state.sounds.title, None);
+                state.audio.play(
+                    state.sounds.gameplay,
+                    true,
+                    Some(0.0),
+                    AlreadyPlayingAction::Nothing,
+                );
+                change_mode(state, Mode::GamePlay);
             }
         }
         Mode::GamePlay => {
-            if !&state.sprites[0].on_screen(state.camera_position, HEIGHT, WIDTH) {
-                state.audio.stop(SoundID(1), None);
-                state
-                    .audio
-                    .play(SoundID(2), true, Some(0.0), AlreadyPlayingAction::Nothing);
-                state.mode = Mode::EndGame;
+            if !&state.sprites[0].on_screen(state.camera.position(), HEIGHT, WIDTH) {
+                state.audio.stop(state.sounds.gameplay, None);
+                state.audio.play(
+                    state.sounds.end,
+                    true,
+                    Some(0.0),
+                    AlreadyPlayingAction::Nothing,
+                );
+                change_mode(state, Mode::EndGame);
             };
 
             tile_collision(state);
@@ -557,67 +910,85 @@ fn update_game(state: &mut GameState, input: &WinitInputHelper) {
                 let score = Score { value: time };
                 state.scores.scores.push(score);
                 state.scores.sort();
-                state.scores.save("data/scores.json");
+                if let Err(e) = state.scores.save(SCORES_PATH) {
+                    eprintln!("{}", e);
+                }
 
-                state.audio.stop(SoundID(1), None);
-                state
-                    .audio
-                    .play(SoundID(2), true, Some(0.0), AlreadyPlayingAction::Nothing);
-                state.mode = Mode::EndGame;
+                state.audio.stop(state.sounds.gameplay, None);
+                state.audio.play(
+                    state.sounds.end,
+                    true,
+                    Some(0.0),
+                    AlreadyPlayingAction::Nothing,
+                );
+                change_mode(state, Mode::EndGame);
             }
 
             state.sprites[0].tick_forward();
 
-            update_camera(state);
+            state.camera.update(Some(&state.sprites[0]));
+
+            // The lamp goes wherever the diver does
+            let diver = state.sprites[0].rect;
+            state.lights.lights[0].position = Vec2f(
+                diver.x + diver.w as f32 / 2.0,
+                diver.y + diver.h as f32 / 2.0,
+            );
         }
         Mode::EndGame => {
-            state.camera_position = Vec2f(0.0, 0.0);
+            state.camera.set_position(Vec2f(0.0, 0.0));
             state.camera_speed = START_SPEED;
             state.level = 0;
             state.sprites[0].rect.x = SPRITE_INITIAL_X;
             state.sprites[0].rect.y = SPRITE_INITIAL_Y;
 
             if input.key_held(VirtualKeyCode::Return) {
-                state.audio.stop(SoundID(2), None);
-                state
-                    .audio
-                    .play(SoundID(1), true, Some(0.0), AlreadyPlayingAction::Nothing);
+                state.audio.stop(state.sounds.end, None);
+                state.audio.play(
+                    state.sounds.gameplay,
+                    true,
+                    Some(0.0),
+                    AlreadyPlayingAction::Nothing,
+                );
                 state.start = Instant::now();
                 let mut bg_tilemaps = vec![];
                 for map in &state.og_tilemaps {
                     bg_tilemaps.push(Rc::new(RefCell::new(map.clone())));
                 }
                 state.bg_tilemaps = bg_tilemaps;
-                state.mode = Mode::GamePlay;
+                change_mode(state, Mode::GamePlay);
             }
         }
     }
 }
 
-fn tile_collision(state: &mut GameState) {
-    let x = state.sprites[0].rect.x;
-    let y = state.sprites[0].rect.y;
+// Where on the diver we check for tiles, in the order
+// top left, top right, bottom left, bottom middle, bottom right, middle left, middle right
+fn collision_points(s: &Sprite) -> Vec<Vec2f> {
+    let x = s.rect.x;
+    let y = s.rect.y;
 
     let tl = Vec2f(x + 18.0, y);
-    let tr = Vec2f(x + state.sprites[0].rect.w as f32 - 18.0, y);
-    let bl = Vec2f(x + 18.0, y + state.sprites[0].rect.h as f32);
-    let bm = Vec2f(
-        x + (state.sprites[0].rect.w) as f32 / 2.0,
-        y + state.sprites[0].rect.h as f32,
-    );
-    let br = Vec2f(
-        x + state.sprites[0].rect.w as f32 - 10.0,
-        y + state.sprites[0].rect.h as f32,
-    );
+    let tr = Vec2f(x + s.rect.w as f32 - 18.0, y);
+    let bl = Vec2f(x + 18.0, y + s.rect.h as f32);
+    let bm = Vec2f(x + (s.rect.w) as f32 / 2.0, y + s.rect.h as f32);
+    let br = Vec2f(x + s.rect.w as f32 - 10.0, y + s.rect.h as f32);
     let ml = Vec2f(
-        x + state.sprites[0].rect.w as f32 + 18.0,
-        (y + state.sprites[0].rect.h as f32) as f32 / 2.0,
+        x + s.rect.w as f32 + 18.0,
+        (y + s.rect.h as f32) as f32 / 2.0,
     );
     let mr = Vec2f(
-        x + (state.sprites[0].rect.w) as f32 / 2.0 - 18.0,
-        y + (state.sprites[0].rect.h as f32) / 2.0,
+        x + (s.rect.w) as f32 / 2.0 - 18.0,
+        y + (s.rect.h as f32) / 2.0,
+    );
+    vec![tl, tr, bl, bm, br, ml, mr]
+}
+
+fn tile_collision(state: &mut GameState) {
+    let posns = collision_points(&state.sprites[0]);
+    let (tl, tr, bl, bm, br, ml, mr) = (
+        posns[0], posns[1], posns[2], posns[3], posns[4], posns[5], posns[6],
     );
-    let posns = vec![tl, tr, bl, bm, br, ml, mr];
 
     for (j, posn) in posns.iter().enumerate() {
         let map_idx = tile_map_at(*posn, &state.bg_tilemaps);
@@ -635,24 +1006,20 @@ fn tile_collision(state: &mut GameState) {
                 } else if t.explode {
                     let tindex = map.tile_index(*posn);
                     map.explode_tiles(tindex, TileID(4), *posn);
+                    // Could be any of the tiles around it, so redraw the lot
+                    state.layer.invalidate();
                 } else if (j == 2 || j == 3 || j == 4) && !t.solid {
                     let tindex = map.tile_index(*posn);
-                    map.replace_tile(tindex, TileID(4));
+                    if map.map[tindex] != TileID(4) {
+                        map.replace_tile(tindex, TileID(4));
+                        state.layer.invalidate_rect(map.tile_rect(tindex));
+                    }
                 }
             }
         }
     }
 }
 
-fn update_camera(state: &mut GameState) {
-    // bottom
-    if state.sprites[0].rect.y + PLAYER_HEIGHT as f32
-        >= state.camera_position.1 + HEIGHT as f32 - 700.0
-    {
-        state.camera_position.1 += 5.0;
-    }
-}
-
 fn tile_map_at(posn: Vec2f, tilemaps: &Vec<Rc<RefCell<Tilemap>>>) -> Option<usize> {
     for (i, map) in tilemaps.iter().enumerate() {
         let map = map.borrow();
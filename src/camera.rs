@@ -0,0 +1,134 @@
+use crate::sprite::Sprite;
+use crate::types::{Rect, Vec2f};
+use rand::Rng;
+
+pub struct Camera {
+    // Top left corner of the view in world coordinates
+    position: Vec2f,
+    // How much of the world we see, usually the screen size
+    pub size: (usize, usize),
+    // Part of the view (relative to its top left) the followed sprite can
+    // wander around in without moving the camera.  It may stick out past the
+    // view, e.g. to never follow the target upwards.
+    pub dead_zone: Rect,
+    // Fraction of the distance to the target covered each tick; 1.0 snaps right there
+    pub smoothing: f32,
+    // Most the camera will move to follow in one tick
+    pub max_speed: Option<f32>,
+    // World area the view has to stay inside
+    pub bounds: Option<Rect>,
+    // Automatic scrolling per tick
+    pub scroll: Vec2f,
+    shake_magnitude: f32,
+    shake_ticks: usize,
+    shake_duration: usize,
+    shake_offset: Vec2f,
+}
+
+impl Camera {
+    pub fn new(position: Vec2f, width: usize, height: usize) -> Self {
+        Self {
+            position,
+            size: (width, height),
+            dead_zone: Rect {
+                x: 0,
+                y: 0,
+                w: width as u16,
+                h: height as u16,
+            },
+            smoothing: 1.0,
+            max_speed: None,
+            bounds: None,
+            scroll: Vec2f(0.0, 0.0),
+            shake_magnitude: 0.0,
+            shake_ticks: 0,
+            shake_duration: 0,
+            shake_offset: Vec2f(0.0, 0.0),
+        }
+    }
+
+    // Where the camera really is, for gameplay checks like Sprite::on_screen
+    pub fn position(&self) -> Vec2f {
+        self.position
+    }
+
+    // Where to draw from this frame (i.e. what goes to Screen::wrap), shake included
+    pub fn screen_position(&self) -> Vec2f {
+        Vec2f(
+            self.position.0 + self.shake_offset.0,
+            self.position.1 + self.shake_offset.1,
+        )
+    }
+
+    // Jump somewhere, e.g. when restarting a level; stops any shake
+    pub fn set_position(&mut self, position: Vec2f) {
+        self.position = position;
+        self.shake_ticks = 0;
+        self.shake_offset = Vec2f(0.0, 0.0);
+        self.clamp();
+    }
+
+    // Shake by up to `magnitude` pixels, dying down over `ticks` ticks
+    pub fn shake(&mut self, magnitude: f32, ticks: usize) {
+        self.shake_magnitude = magnitude.abs();
+        self.shake_ticks = ticks;
+        self.shake_duration = ticks;
+    }
+
+    // Call once per simulation tick, with the sprite to follow if any
+    pub fn update(&mut self, target: Option<&Sprite>) {
+        self.position.0 += self.scroll.0;
+        self.position.1 += self.scroll.1;
+        if let Some(s) = target {
+            let dx = Self::distance_outside(
+                s.rect.x,
+                s.rect.x + s.rect.w as f32,
+                self.position.0 + self.dead_zone.x as f32,
+                self.position.0 + (self.dead_zone.x + self.dead_zone.w as i32) as f32,
+            );
+            let dy = Self::distance_outside(
+                s.rect.y,
+                s.rect.y + s.rect.h as f32,
+                self.position.1 + self.dead_zone.y as f32,
+                self.position.1 + (self.dead_zone.y + self.dead_zone.h as i32) as f32,
+            );
+            let mut step = Vec2f(dx * self.smoothing, dy * self.smoothing);
+            if let Some(max) = self.max_speed {
+                step = Vec2f(step.0.max(-max).min(max), step.1.max(-max).min(max));
+            }
+            self.position.0 += step.0;
+            self.position.1 += step.1;
+        }
+        self.clamp();
+        if self.shake_ticks > 0 {
+            // Shake less and less as the timer runs down
+            let m = self.shake_magnitude * self.shake_ticks as f32 / self.shake_duration as f32;
+            let mut rng = rand::thread_rng();
+            self.shake_offset = Vec2f(rng.gen_range(-m..=m), rng.gen_range(-m..=m));
+            self.shake_ticks -= 1;
+        } else {
+            self.shake_offset = Vec2f(0.0, 0.0);
+        }
+    }
+
+    // How far the span lo..hi sticks out of zone_lo..zone_hi: negative past
+    // the low edge, positive past the high edge, zero if it's inside
+    fn distance_outside(lo: f32, hi: f32, zone_lo: f32, zone_hi: f32) -> f32 {
+        if lo < zone_lo {
+            lo - zone_lo
+        } else if hi > zone_hi {
+            hi - zone_hi
+        } else {
+            0.0
+        }
+    }
+
+    fn clamp(&mut self) {
+        if let Some(b) = self.bounds {
+            let max_x = (b.x + b.w as i32 - self.size.0 as i32).max(b.x) as f32;
+            let max_y = (b.y + b.h as i32 - self.size.1 as i32).max(b.y) as f32;
+            self.position.0 = self.position.0.max(b.x as f32).min(max_x);
+            self.position.1 = self.position.1.max(b.y as f32).min(max_y);
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use crate::screen::Screen;
+use crate::texture::{unpremultiply, Texture};
+use crate::types::{Rgba, Vec2f};
+use image::{ImageResult, RgbaImage};
+use std::path::Path;
+
+/// An owned, offscreen framebuffer.
+/// It draws exactly like the window does, so frames can be rendered and
+/// checked without a window, a GPU or a display.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Canvas {
+    buffer: Vec<u8>,
+    width: usize,
+    height: usize,
+    depth: usize,
+}
+
+impl Default for Canvas {
+    fn default() -> Self {
+        Self::new(0, 0)
+    }
+}
+
+impl Canvas {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            buffer: vec![0; width * height * 4],
+            width,
+            height,
+            depth: 4,
+        }
+    }
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+    pub fn buffer(&self) -> &[u8] {
+        &self.buffer
+    }
+    // Just like wrapping the pixels frame, so all the drawing calls and
+    // the Draw*Ext traits work on a canvas too.
+    pub fn screen(&mut self, position: Vec2f) -> Screen<'_> {
+        Screen::wrap(
+            &mut self.buffer,
+            self.width,
+            self.height,
+            self.depth,
+            position,
+        )
+    }
+    pub fn pixel(&self, x: usize, y: usize) -> Rgba {
+        assert!(x < self.width && y < self.height);
+        let idx = y * self.width * self.depth + x * self.depth;
+        let px = &self.buffer[idx..(idx + self.depth)];
+        Rgba(px[0], px[1], px[2], px[3])
+    }
+    // Keep what was drawn as a texture, e.g. to bitblt it somewhere else
+    pub fn into_texture(self) -> Texture {
+        Texture::from_premultiplied(self.width, self.height, self.buffer)
+    }
+    // Back to straight alpha, the same as Texture::to_image
+    pub fn to_image(&self) -> RgbaImage {
+        let mut image = self.buffer.clone();
+        unpremultiply(&mut image);
+        RgbaImage::from_raw(self.width as u32, self.height as u32, image)
+            .expect("Canvas buffer has the wrong size")
+    }
+    // Opaque pixels come back from the file exactly as they were drawn;
+    // see-through ones get rounded, so compare `buffer`s for exact bytes
+    pub fn save_png(&self, path: &Path) -> ImageResult<()> {
+        self.to_image()
+            .save_with_format(path, image::ImageFormat::Png)
+    }
+}
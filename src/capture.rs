@@ -0,0 +1,161 @@
+use crate::screen::Screen;
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, ImageError, ImageResult, RgbaImage};
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// How many grabbed frames can wait for the worker.  If encoding falls
+// further behind than this, frames get dropped rather than piling up.
+const QUEUED_FRAMES: usize = 8;
+
+// Copies the framebuffer out exactly as the window shows it, before any
+// window scaling.  The window ignores alpha, so the copy is made opaque too;
+// otherwise anything cleared to transparent would vanish in image viewers.
+pub fn grab(screen: &Screen) -> RgbaImage {
+    let (w, h) = screen.size();
+    let mut img = RgbaImage::from_raw(w as u32, h as u32, screen.framebuffer.to_vec())
+        .expect("Framebuffer has the wrong size");
+    for px in img.pixels_mut() {
+        px.0[3] = 255;
+    }
+    img
+}
+
+// Saves the current frame as dir/screenshot-<timestamp>.png and returns its path
+pub fn screenshot(screen: &Screen, dir: &Path) -> ImageResult<PathBuf> {
+    fs::create_dir_all(dir).map_err(ImageError::IoError)?;
+    let path = dir.join(format!("screenshot-{}.png", timestamp()));
+    grab(screen).save_with_format(&path, image::ImageFormat::Png)?;
+    Ok(path)
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum RecordFormat {
+    // recording-<timestamp>/frame-00000.png, frame-00001.png, ...
+    PngSequence,
+    // recording-<timestamp>.gif, looping forever
+    Gif,
+}
+
+// Saves every Nth frame it's given.  Encoding happens on a worker thread,
+// so recording doesn't stall the game.
+pub struct Recorder {
+    every: usize,
+    seen: usize,
+    last: Option<Instant>,
+    dropped: usize,
+    path: PathBuf,
+    frames: SyncSender<(RgbaImage, Duration)>,
+    worker: JoinHandle<ImageResult<usize>>,
+}
+
+impl Recorder {
+    pub fn start(dir: &Path, format: RecordFormat, every: usize) -> ImageResult<Self> {
+        assert!(every > 0, "Can't record every 0th frame");
+        let stem = format!("recording-{}", timestamp());
+        let (tx, rx) = mpsc::sync_channel::<(RgbaImage, Duration)>(QUEUED_FRAMES);
+        let (path, worker) = match format {
+            RecordFormat::PngSequence => {
+                let path = dir.join(stem);
+                fs::create_dir_all(&path).map_err(ImageError::IoError)?;
+                let out = path.clone();
+                let worker = thread::spawn(move || {
+                    let mut count = 0;
+                    for (img, _) in rx {
+                        let file = out.join(format!("frame-{:05}.png", count));
+                        img.save_with_format(file, image::ImageFormat::Png)?;
+                        count += 1;
+                    }
+                    Ok(count)
+                });
+                (path, worker)
+            }
+            RecordFormat::Gif => {
+                fs::create_dir_all(dir).map_err(ImageError::IoError)?;
+                let path = dir.join(stem + ".gif");
+                let file = File::create(&path).map_err(ImageError::IoError)?;
+                let worker = thread::spawn(move || {
+                    let mut gif = GifEncoder::new_with_speed(BufWriter::new(file), 10);
+                    gif.set_repeat(Repeat::Infinite)?;
+                    let mut count = 0;
+                    // A gif frame needs to know how long it stays up, which is
+                    // only known once the next one arrives; so lag one behind.
+                    let mut pending: Option<RgbaImage> = None;
+                    let mut last_delay = Duration::from_millis(100);
+                    for (img, since_last) in rx {
+                        if let Some(prev) = pending.take() {
+                            gif.encode_frame(gif_frame(prev, since_last))?;
+                            count += 1;
+                            last_delay = since_last;
+                        }
+                        pending = Some(img);
+                    }
+                    if let Some(prev) = pending {
+                        gif.encode_frame(gif_frame(prev, last_delay))?;
+                        count += 1;
+                    }
+                    Ok(count)
+                });
+                (path, worker)
+            }
+        };
+        Ok(Self {
+            every,
+            seen: 0,
+            last: None,
+            dropped: 0,
+            path,
+            frames: tx,
+            worker,
+        })
+    }
+
+    // Where the recording is going: a directory for PNGs, a file for gifs
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // How many frames were skipped because the worker couldn't keep up
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    // Call once per rendered frame, after drawing and before it gets presented
+    pub fn capture(&mut self, screen: &Screen) {
+        if self.seen.is_multiple_of(self.every) {
+            let now = Instant::now();
+            let since_last = self.last.map_or(Duration::from_millis(0), |t| now - t);
+            match self.frames.try_send((grab(screen), since_last)) {
+                // A dropped frame's time goes to the next one that's sent
+                Ok(()) => self.last = Some(now),
+                Err(TrySendError::Full(_)) => self.dropped += 1,
+                // If the worker died it kept its error for finish to report
+                Err(TrySendError::Disconnected(_)) => {}
+            }
+        }
+        self.seen += 1;
+    }
+
+    // Stop recording and wait for everything to be written.
+    // Returns how many frames were saved.
+    pub fn finish(self) -> ImageResult<usize> {
+        drop(self.frames);
+        self.worker.join().expect("Recording thread panicked")
+    }
+}
+
+fn gif_frame(img: RgbaImage, shown_for: Duration) -> Frame {
+    let ms = shown_for.as_millis().max(1).min(u32::MAX as u128) as u32;
+    Frame::from_parts(img, 0, 0, Delay::from_numer_denom_ms(ms, 1))
+}
+
+// Milliseconds since the epoch, so names sort by when they were taken
+fn timestamp() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis())
+}
@@ -15,13 +15,13 @@ pub struct Mobile {
 }
 
 #[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
-enum ColliderID {
+pub(crate) enum ColliderID {
     Static(usize),
     Dynamic(usize),
 }
 
 #[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
-enum Side {
+pub(crate) enum Side {
     Left,
     Right,
     Top,
@@ -30,10 +30,10 @@ enum Side {
 
 #[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
 pub struct Contact {
-    a: ColliderID,
-    b: ColliderID,
-    mtv: (i32, i32),
-    side_a: Side,
+    pub(crate) a: ColliderID,
+    pub(crate) b: ColliderID,
+    pub(crate) mtv: (i32, i32),
+    pub(crate) side_a: Side,
 }
 
 pub struct Collision {}
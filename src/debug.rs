@@ -0,0 +1,254 @@
+use crate::collision::{ColliderID, Contact, Side};
+use crate::obstacle::Obstacle;
+use crate::screen::Screen;
+use crate::sprite::Sprite;
+use crate::text::{DrawTextExt, Font, Text};
+use crate::tiles::{Tilemap, TILE_SZ};
+use crate::types::{Rect, Rectf, Rgba, Vec2f};
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+// How many frames the FPS readout averages over
+const FRAME_WINDOW: usize = 60;
+
+const SPRITE_COLOR: Rgba = Rgba(0, 255, 0, 255);
+const OBSTACLE_COLOR: Rgba = Rgba(255, 0, 255, 255);
+const GRID_COLOR: Rgba = Rgba(255, 255, 255, 60);
+const SOLID_COLOR: Rgba = Rgba(255, 0, 0, 90);
+const CONTACT_COLOR: Rgba = Rgba(255, 255, 0, 255);
+const POINT_COLOR: Rgba = Rgba(0, 255, 255, 255);
+
+// Draws collision and timing info on top of the game.
+// Every draw call does nothing while the overlay is switched off, so games
+// can leave the calls in and just flip `toggle` from a key.
+pub struct DebugOverlay {
+    pub enabled: bool,
+    frame_times: VecDeque<Duration>,
+    last_frame: Option<Instant>,
+    // Share of the last frame that was drawn again, if the game says
+    redrawn: Option<f32>,
+}
+
+impl Default for DebugOverlay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DebugOverlay {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            frame_times: VecDeque::with_capacity(FRAME_WINDOW),
+            last_frame: None,
+            redrawn: None,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    // Call once per rendered frame so the overlay can time them
+    pub fn frame(&mut self) {
+        let now = Instant::now();
+        if let Some(last) = self.last_frame {
+            if self.frame_times.len() == FRAME_WINDOW {
+                self.frame_times.pop_front();
+            }
+            self.frame_times.push_back(now - last);
+        }
+        self.last_frame = Some(now);
+    }
+
+    // For games with a StaticLayer: how many of the frame's pixels it had
+    // to put back or draw over, out of all of them
+    pub fn redrawn(&mut self, pixels: usize, total: usize) {
+        self.redrawn = Some(pixels as f32 / total.max(1) as f32);
+    }
+
+    // Average frame time in milliseconds over the last FRAME_WINDOW frames
+    pub fn frame_time(&self) -> f32 {
+        if self.frame_times.is_empty() {
+            return 0.0;
+        }
+        let total: Duration = self.frame_times.iter().sum();
+        total.as_secs_f32() * 1000.0 / self.frame_times.len() as f32
+    }
+
+    pub fn fps(&self) -> f32 {
+        let ft = self.frame_time();
+        if ft > 0.0 {
+            1000.0 / ft
+        } else {
+            0.0
+        }
+    }
+
+    // Sprite::rect boxes
+    pub fn draw_sprites(&self, screen: &mut Screen, sprites: &[Sprite]) {
+        if !self.enabled {
+            return;
+        }
+        for s in sprites {
+            outline(screen, s.rect, SPRITE_COLOR);
+        }
+    }
+
+    // Obstacle::rect colliders
+    pub fn draw_obstacles(&self, screen: &mut Screen, obstacles: &[Obstacle]) {
+        if !self.enabled {
+            return;
+        }
+        for r in obstacles.iter().filter_map(|o| o.rect) {
+            outline(screen, to_rectf(r), OBSTACLE_COLOR);
+        }
+    }
+
+    // Grid lines for the visible part of the map, with solid tiles shaded
+    pub fn draw_tilemap(&self, screen: &mut Screen, map: &Tilemap) {
+        if !self.enabled {
+            return;
+        }
+        let b = screen.bounds();
+        let tsz = TILE_SZ as f32;
+        let tile_range = |lo: f32, len: u16, origin: f32, max: usize| {
+            let first = ((lo - origin) / tsz).floor().max(0.0).min(max as f32) as usize;
+            let last = ((lo + len as f32 - origin) / tsz)
+                .ceil()
+                .max(0.0)
+                .min(max as f32) as usize;
+            (first, last)
+        };
+        let (left, right) = tile_range(b.x as f32, b.w, map.position.0, map.dims.0);
+        let (top, bot) = tile_range(b.y as f32, b.h, map.position.1, map.dims.1);
+        for y in top..bot {
+            for x in left..right {
+                let id = map.map[y * map.dims.0 + x];
+                if map.tileset[id].solid {
+                    screen.rect_blend(
+                        Rect {
+                            x: (map.position.0 + (x * TILE_SZ) as f32) as i32,
+                            y: (map.position.1 + (y * TILE_SZ) as f32) as i32,
+                            w: TILE_SZ as u16,
+                            h: TILE_SZ as u16,
+                        },
+                        SOLID_COLOR,
+                    );
+                }
+            }
+        }
+        let y0 = map.position.1 + top as f32 * tsz;
+        let y1 = map.position.1 + bot as f32 * tsz;
+        for x in left..=right {
+            let xpx = map.position.0 + x as f32 * tsz;
+            screen.line_blend(Vec2f(xpx, y0), Vec2f(xpx, y1), GRID_COLOR);
+        }
+        let x0 = map.position.0 + left as f32 * tsz;
+        let x1 = map.position.0 + right as f32 * tsz;
+        for y in top..=bot {
+            let ypx = map.position.1 + y as f32 * tsz;
+            screen.line_blend(Vec2f(x0, ypx), Vec2f(x1, ypx), GRID_COLOR);
+        }
+    }
+
+    // Each contact as a line from the middle of its dynamic collider, pointing
+    // the way restitution will push it and as far
+    pub fn draw_contacts(
+        &self,
+        screen: &mut Screen,
+        contacts: &[Contact],
+        statics: &[Obstacle],
+        dynamics: &[Sprite],
+    ) {
+        if !self.enabled {
+            return;
+        }
+        for c in contacts {
+            let a = match c.a {
+                ColliderID::Dynamic(i) => dynamics[i].rect,
+                ColliderID::Static(i) => match statics[i].rect {
+                    Some(r) => to_rectf(r),
+                    None => continue,
+                },
+            };
+            let center = Vec2f(a.x + a.w as f32 / 2.0, a.y + a.h as f32 / 2.0);
+            // Restitution only ever pushes by mtv.1, along the side's axis,
+            // and splits it between two dynamics
+            let push = match c.b {
+                ColliderID::Dynamic(_) => (c.mtv.1 / 2) as f32,
+                ColliderID::Static(_) => c.mtv.1 as f32,
+            };
+            let (dx, dy) = match c.side_a {
+                Side::Top => (0.0, push),
+                Side::Bottom => (0.0, -push),
+                Side::Left => (push, 0.0),
+                Side::Right => (-push, 0.0),
+            };
+            screen.line(center, Vec2f(center.0 + dx, center.1 + dy), CONTACT_COLOR);
+            cross(screen, center, CONTACT_COLOR);
+        }
+    }
+
+    // Sample points, like the ones the games test against tiles
+    pub fn draw_points(&self, screen: &mut Screen, points: &[Vec2f]) {
+        if !self.enabled {
+            return;
+        }
+        for p in points {
+            cross(screen, *p, POINT_COLOR);
+        }
+    }
+
+    // FPS and frame time in the top left of the screen, and how much of the
+    // last frame was redrawn if that's known
+    pub fn draw_stats(&self, screen: &mut Screen, font: &Rc<Font>) {
+        if !self.enabled {
+            return;
+        }
+        let b = screen.bounds();
+        let mut stats = format!("{:.0} FPS {:.1}MS", self.fps(), self.frame_time());
+        if let Some(r) = self.redrawn {
+            stats.push_str(&format!(" {:.0}% REDRAWN", r * 100.0));
+        }
+        let mut text = Text::new(
+            Rc::clone(font),
+            &stats,
+            Vec2f(b.x as f32 + 4.0, b.y as f32 + 4.0),
+        );
+        screen.rect_blend(
+            Rect {
+                x: b.x,
+                y: b.y,
+                w: text.length as u16 + 8,
+                h: 24,
+            },
+            Rgba(0, 0, 0, 160),
+        );
+        screen.draw_text(&mut text);
+    }
+}
+
+fn to_rectf(r: Rect) -> Rectf {
+    Rectf {
+        x: r.x as f32,
+        y: r.y as f32,
+        w: r.w,
+        h: r.h,
+    }
+}
+
+fn outline(screen: &mut Screen, r: Rectf, col: Rgba) {
+    let x1 = r.x + r.w as f32;
+    let y1 = r.y + r.h as f32;
+    screen.line(Vec2f(r.x, r.y), Vec2f(x1, r.y), col);
+    screen.line(Vec2f(x1, r.y), Vec2f(x1, y1), col);
+    screen.line(Vec2f(x1, y1), Vec2f(r.x, y1), col);
+    screen.line(Vec2f(r.x, y1), Vec2f(r.x, r.y), col);
+}
+
+fn cross(screen: &mut Screen, Vec2f(x, y): Vec2f, col: Rgba) {
+    screen.line(Vec2f(x - 2.0, y), Vec2f(x + 3.0, y), col);
+    screen.line(Vec2f(x, y - 2.0), Vec2f(x, y + 3.0), col);
+}
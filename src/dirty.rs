@@ -0,0 +1,262 @@
+use crate::canvas::Canvas;
+use crate::screen::Screen;
+use crate::types::{Rect, Vec2f};
+
+// Dirty areas are tracked in square cells this many pixels across
+const CELL: usize = 16;
+
+// Which parts of a framebuffer have been drawn to, roughly: it's kept as a
+// grid of cells, so an area comes back a little bigger than what was marked.
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+pub struct DirtyRegions {
+    width: usize,
+    height: usize,
+    cols: usize,
+    rows: usize,
+    cells: Vec<bool>,
+}
+
+impl DirtyRegions {
+    pub fn new(width: usize, height: usize) -> Self {
+        let cols = width.div_ceil(CELL);
+        let rows = height.div_ceil(CELL);
+        Self {
+            width,
+            height,
+            cols,
+            rows,
+            cells: vec![false; cols * rows],
+        }
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    // r is in framebuffer coordinates; anything outside the framebuffer is ignored
+    pub fn mark(&mut self, r: Rect) {
+        let x0 = r.x.max(0) as usize;
+        let y0 = r.y.max(0) as usize;
+        let x1 = ((r.x + r.w as i32).max(0) as usize).min(self.width);
+        let y1 = ((r.y + r.h as i32).max(0) as usize).min(self.height);
+        if x0 >= x1 || y0 >= y1 {
+            return;
+        }
+        for row in (y0 / CELL)..=((y1 - 1) / CELL) {
+            let cells = &mut self.cells[(row * self.cols)..((row + 1) * self.cols)];
+            for c in cells[(x0 / CELL)..=((x1 - 1) / CELL)].iter_mut() {
+                *c = true;
+            }
+        }
+    }
+
+    pub fn mark_all(&mut self) {
+        for c in self.cells.iter_mut() {
+            *c = true;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for c in self.cells.iter_mut() {
+            *c = false;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.cells.contains(&true)
+    }
+
+    // Marks everything that's marked in other, which should be the same size
+    pub fn add(&mut self, other: &DirtyRegions) {
+        assert_eq!(
+            self.size(),
+            other.size(),
+            "Dirty regions are different sizes"
+        );
+        for (c, o) in self.cells.iter_mut().zip(other.cells.iter()) {
+            *c |= *o;
+        }
+    }
+
+    // How many pixels the marked cells cover
+    pub fn area(&self) -> usize {
+        self.rects()
+            .iter()
+            .map(|r| r.w as usize * r.h as usize)
+            .sum()
+    }
+
+    // The marked area as a few rects, in framebuffer coordinates.
+    // Runs of cells in a row are joined up, and so are matching runs in the
+    // rows below, so a big square comes back as one rect.
+    pub fn rects(&self) -> Vec<Rect> {
+        // (first col, last col, first row, last row) of each rect so far
+        let mut runs: Vec<(usize, usize, usize, usize)> = vec![];
+        for row in 0..self.rows {
+            let cells = &self.cells[(row * self.cols)..((row + 1) * self.cols)];
+            let mut col = 0;
+            while col < self.cols {
+                if !cells[col] {
+                    col += 1;
+                    continue;
+                }
+                let start = col;
+                while col < self.cols && cells[col] {
+                    col += 1;
+                }
+                let end = col - 1;
+                match runs
+                    .iter_mut()
+                    .find(|r| r.0 == start && r.1 == end && r.3 + 1 == row)
+                {
+                    Some(r) => r.3 = row,
+                    None => runs.push((start, end, row, row)),
+                }
+            }
+        }
+        runs.into_iter()
+            .map(|(c0, c1, r0, r1)| {
+                let x = c0 * CELL;
+                let y = r0 * CELL;
+                Rect {
+                    x: x as i32,
+                    y: y as i32,
+                    w: (((c1 + 1) * CELL).min(self.width) - x) as u16,
+                    h: (((r1 + 1) * CELL).min(self.height) - y) as u16,
+                }
+            })
+            .collect()
+    }
+}
+
+// A cached copy of everything that doesn't change from frame to frame
+// (backgrounds, tilemaps, ...), so a frame only has to put back the parts
+// that moving things drew over last frame instead of redrawing it all.
+//
+// Each frame, call `begin` before drawing anything else and `end` after
+// the last thing that touches the framebuffer.  In between, Screen keeps
+// track of what gets drawn over.  The cache is redrawn by itself when the
+// screen's position or size changes; anything else that changes what the
+// static part looks like has to `invalidate` it.
+#[derive(Default)]
+pub struct StaticLayer {
+    cache: Canvas,
+    position: Option<Vec2f>,
+    // Parts of the cache to redraw, in world coordinates
+    stale: Vec<Rect>,
+    all_stale: bool,
+    // Parts to put back from the cache as they are, in world coordinates
+    refresh: Vec<Rect>,
+    // What was drawn over the static part last frame
+    touched: DirtyRegions,
+    // What begin put back this frame
+    restored: DirtyRegions,
+    // Pixels put back or drawn over last frame
+    redrawn: usize,
+}
+
+impl StaticLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Redraw everything next frame, e.g. for a new game mode
+    pub fn invalidate(&mut self) {
+        self.all_stale = true;
+    }
+
+    // Redraw part of the world next frame, e.g. a tile that changed
+    pub fn invalidate_rect(&mut self, r: Rect) {
+        self.stale.push(r);
+    }
+
+    // Put part of the world back from the cache next frame without
+    // redrawing it, e.g. where the lighting over it has changed
+    pub fn refresh_rect(&mut self, r: Rect) {
+        self.refresh.push(r);
+    }
+
+    // What `begin` put back from the cache this frame, in framebuffer
+    // coordinates; everywhere else still has last frame's pixels
+    pub fn restored(&self) -> &DirtyRegions {
+        &self.restored
+    }
+
+    // How many pixels last frame put back or drew over.  The rest were
+    // left alone, which is the work the layer saves.
+    pub fn redrawn(&self) -> usize {
+        self.redrawn
+    }
+
+    // Brings the screen back to just the static layer.  `draw` is called to
+    // draw the static things into the cache whenever some of it is stale;
+    // it's clipped to the stale part, so it can always draw everything.
+    pub fn begin<F: FnMut(&mut Screen)>(&mut self, screen: &mut Screen, mut draw: F) {
+        let (w, h) = screen.size();
+        let position = screen.position();
+        if self.cache.size() != (w, h) {
+            self.cache = Canvas::new(w, h);
+            self.all_stale = true;
+        }
+        if self.position != Some(position) {
+            self.position = Some(position);
+            self.all_stale = true;
+        }
+        let mut restore = std::mem::take(&mut self.touched);
+        // First frame, or `end` went missing last frame
+        if restore.size() != (w, h) {
+            restore = DirtyRegions::new(w, h);
+            self.all_stale = true;
+        }
+        {
+            let mut cache = self.cache.screen(position);
+            if self.all_stale {
+                draw(&mut cache);
+                restore.mark_all();
+            } else {
+                for r in self.stale.iter() {
+                    cache.push_clip(*r);
+                    draw(&mut cache);
+                    cache.pop();
+                    restore.mark(Rect {
+                        x: r.x - position.0 as i32,
+                        y: r.y - position.1 as i32,
+                        ..*r
+                    });
+                }
+            }
+        }
+        for r in self.refresh.iter() {
+            restore.mark(Rect {
+                x: r.x - position.0 as i32,
+                y: r.y - position.1 as i32,
+                ..*r
+            });
+        }
+        self.stale.clear();
+        self.refresh.clear();
+        self.all_stale = false;
+        let pitch = w * 4;
+        let cached = self.cache.buffer();
+        for r in restore.rects() {
+            for y in (r.y as usize)..(r.y as usize + r.h as usize) {
+                let start = y * pitch + r.x as usize * 4;
+                let end = start + r.w as usize * 4;
+                screen.framebuffer[start..end].copy_from_slice(&cached[start..end]);
+            }
+        }
+        self.restored.clone_from(&restore);
+        restore.clear();
+        screen.track(restore);
+    }
+
+    // Stops tracking, remembering what needs cleaning up next frame
+    pub fn end(&mut self, screen: &mut Screen) {
+        if let Some(touched) = screen.untrack() {
+            let mut redrawn = self.restored.clone();
+            redrawn.add(&touched);
+            self.redrawn = redrawn.area();
+            self.touched = touched;
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use kira::manager::error::{LoadSoundError, SetupError};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+// Everything that can go wrong loading or saving a game's assets.
+// Most of them say which file it was, since that's usually the fix.
+#[derive(Debug)]
+pub enum Error {
+    // Missing, unreadable or not an image we understand
+    Image {
+        path: PathBuf,
+        source: image::ImageError,
+    },
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    Json {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    Sound {
+        path: PathBuf,
+        source: LoadSoundError,
+    },
+    // No sound card, or it wouldn't start
+    AudioSetup(SetupError),
+    // A tileset or tilemap that doesn't add up
+    Tiles(String),
+    // Images that won't pack, or a manifest that doesn't match its atlas
+    Atlas(String),
+    // Sprite sheet JSON that's readable but doesn't make sense
+    Sheet {
+        path: PathBuf,
+        why: String,
+    },
+    // A texture reloaded from a file that's a different size now
+    Resized {
+        path: PathBuf,
+        was: (usize, usize),
+        now: (usize, usize),
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    // Which file it was about, if any
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Error::Image { path, .. }
+            | Error::Io { path, .. }
+            | Error::Json { path, .. }
+            | Error::Sound { path, .. }
+            | Error::Sheet { path, .. }
+            | Error::Resized { path, .. } => Some(path),
+            Error::AudioSetup(_) | Error::Tiles(_) | Error::Atlas(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Image { path, source } => {
+                write!(f, "Couldn't load image {}: {}", path.display(), source)
+            }
+            Error::Io { path, source } => {
+                write!(f, "Couldn't access {}: {}", path.display(), source)
+            }
+            Error::Json { path, source } => {
+                write!(f, "Bad JSON in {}: {}", path.display(), source)
+            }
+            Error::Sound { path, source } => {
+                write!(f, "Couldn't load sound {}: {}", path.display(), source)
+            }
+            Error::AudioSetup(source) => write!(f, "Couldn't start audio: {}", source),
+            Error::Tiles(why) => write!(f, "Bad tiles: {}", why),
+            Error::Atlas(why) => write!(f, "Bad atlas: {}", why),
+            Error::Sheet { path, why } => {
+                write!(f, "Bad sprite sheet {}: {}", path.display(), why)
+            }
+            Error::Resized { path, was, now } => write!(
+                f,
+                "{} changed size from {}x{} to {}x{}; restart to use it",
+                path.display(),
+                was.0,
+                was.1,
+                now.0,
+                now.1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Image { source, .. } => Some(source),
+            Error::Io { source, .. } => Some(source),
+            Error::Json { source, .. } => Some(source),
+            Error::Sound { source, .. } => Some(source),
+            Error::AudioSetup(source) => Some(source),
+            Error::Tiles(_) | Error::Atlas(_) | Error::Sheet { .. } | Error::Resized { .. } => None,
+        }
+    }
+}
+
+impl From<SetupError> for Error {
+    fn from(e: SetupError) -> Self {
+        Error::AudioSetup(e)
+    }
+}
@@ -5,14 +5,29 @@ pub mod screen;
 use crate::screen::*;
 // Lazy glob imports
 pub mod collision;
+pub mod debug;
+pub mod dirty;
+// Error and Result for anything that loads from disk
+pub mod error;
+pub use crate::error::{Error, Result};
 // Texture has our image loading and processing stuff
 pub mod texture;
 use crate::texture::*;
 pub mod animation;
+pub mod assets;
+pub mod atlas;
 pub mod audio;
 pub mod background;
+pub mod camera;
+pub mod canvas;
+pub mod capture;
+pub mod lighting;
+pub mod nineslice;
 pub mod obstacle;
+pub mod postfx;
+pub mod queue;
 pub mod scores;
+pub mod sheet;
 pub mod sprite;
 use crate::sprite::*;
 pub mod text;
@@ -20,3 +35,4 @@ pub mod tiles;
 pub use crate::tiles::*;
 pub mod types;
 use crate::types::*;
+pub mod watch;
@@ -0,0 +1,240 @@
+use crate::dirty::DirtyRegions;
+use crate::screen::Screen;
+use crate::tiles::{Tilemap, TILE_SZ};
+use crate::types::{Rect, Rgba, Vec2f};
+
+// Light is worked out every CELL pixels and smoothed out in between
+const CELL: usize = 8;
+// How far apart shadow rays check for solid tiles
+const RAY_STEP: f32 = (TILE_SZ / 4) as f32;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Light {
+    pub position: Vec2f,
+    // Nothing past this far gets any light
+    pub radius: f32,
+    pub color: Rgba,
+    // 1.0 dims evenly out to the radius; higher keeps the light tighter
+    pub falloff: f32,
+}
+
+impl Light {
+    pub fn new(position: Vec2f, radius: f32, color: Rgba) -> Self {
+        Self {
+            position,
+            radius,
+            color,
+            falloff: 1.0,
+        }
+    }
+
+    // The square around everything it can reach, in world coordinates
+    pub fn area(&self) -> Rect {
+        let r = self.radius.ceil() as i32;
+        Rect {
+            x: self.position.0.floor() as i32 - r,
+            y: self.position.1.floor() as i32 - r,
+            w: (2 * r + 1) as u16,
+            h: (2 * r + 1) as u16,
+        }
+    }
+}
+
+// Ambient light for part of the world, blending from top to bottom
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct AmbientRegion {
+    pub area: Rect,
+    pub top: Rgba,
+    pub bottom: Rgba,
+}
+
+impl AmbientRegion {
+    pub fn solid(area: Rect, color: Rgba) -> Self {
+        Self {
+            area,
+            top: color,
+            bottom: color,
+        }
+    }
+}
+
+// Darkness over the world, with lights cutting through it.
+// Everything drawn before `apply` gets multiplied by how lit it is, so draw
+// the world, apply, then draw anything (like a HUD) that should stay bright.
+pub struct LightMap {
+    // For anywhere no region covers
+    pub ambient: Rgba,
+    // Later regions win where they overlap
+    pub regions: Vec<AmbientRegion>,
+    pub lights: Vec<Light>,
+    grid: Vec<[f32; 3]>,
+    // The lights as they were for the last apply
+    applied: Vec<Light>,
+}
+
+impl LightMap {
+    pub fn new(ambient: Rgba) -> Self {
+        Self {
+            ambient,
+            regions: vec![],
+            lights: vec![],
+            grid: vec![],
+            applied: vec![],
+        }
+    }
+
+    // How lit a point in the world is, from 0.0 to 1.0 per channel.
+    // Solid tiles in `blockers` cast shadows.
+    pub fn light_at(&self, p: Vec2f, blockers: &[&Tilemap]) -> [f32; 3] {
+        let mut l = self.ambient_at(p);
+        let target = solid_tile(blockers, p);
+        for light in self.lights.iter() {
+            let (dx, dy) = (p.0 - light.position.0, p.1 - light.position.1);
+            let d = (dx * dx + dy * dy).sqrt();
+            if d >= light.radius || blocked(light.position, p, target, blockers) {
+                continue;
+            }
+            let k = (1.0 - d / light.radius).powf(light.falloff);
+            l[0] += light.color.0 as f32 / 255.0 * k;
+            l[1] += light.color.1 as f32 / 255.0 * k;
+            l[2] += light.color.2 as f32 / 255.0 * k;
+        }
+        [l[0].min(1.0), l[1].min(1.0), l[2].min(1.0)]
+    }
+
+    // Darkens the whole framebuffer, ignoring any clip
+    pub fn apply(&mut self, screen: &mut Screen, blockers: &[&Tilemap]) {
+        let (w, h) = screen.size();
+        let all = Rect {
+            x: 0,
+            y: 0,
+            w: w as u16,
+            h: h as u16,
+        };
+        self.darken(screen, all, blockers);
+        self.applied = self.lights.clone();
+        screen.mark_all_dirty();
+    }
+
+    // Darkens just `regions` of the framebuffer, for use with a StaticLayer
+    // whose cache is unlit: pass it `restored`, and everywhere else keeps the
+    // light it got on an earlier frame.  So anything to be lit has to be drawn
+    // inside those regions (`refresh_rect` where it'll go), and so do the
+    // `changed` areas.  Nothing new is marked dirty; once lit, the restored
+    // parts look the way they should until something draws over them.
+    pub fn apply_to(&mut self, screen: &mut Screen, regions: &DirtyRegions, blockers: &[&Tilemap]) {
+        for r in regions.rects() {
+            self.darken(screen, r, blockers);
+        }
+        self.applied = self.lights.clone();
+    }
+
+    // Where the lights look different from the last apply, in world
+    // coordinates: around each one that's moved or changed, before and after.
+    // Changing the ambient light or the blockers isn't noticed here.
+    pub fn changed(&self) -> Vec<Rect> {
+        let mut areas = vec![];
+        for i in 0..self.lights.len().max(self.applied.len()) {
+            let (now, before) = (self.lights.get(i), self.applied.get(i));
+            if now != before {
+                areas.extend(now.map(Light::area));
+                areas.extend(before.map(Light::area));
+            }
+        }
+        areas
+    }
+
+    // Multiplies part of the framebuffer (in framebuffer coordinates) by the light
+    fn darken(&mut self, screen: &mut Screen, r: Rect, blockers: &[&Tilemap]) {
+        let (w, _) = screen.size();
+        let Vec2f(ox, oy) = screen.position();
+        let (x0, y0) = (r.x as usize, r.y as usize);
+        let (x1, y1) = (x0 + r.w as usize, y0 + r.h as usize);
+        if x0 >= x1 || y0 >= y1 {
+            return;
+        }
+        // Samples on the same CELL grid as the whole framebuffer would use,
+        // so neighbouring parts line up; one more past the end so every
+        // pixel has four around it
+        let (gx0, gy0) = (x0 / CELL, y0 / CELL);
+        let gw = (x1 - 1) / CELL + 2 - gx0;
+        let gh = (y1 - 1) / CELL + 2 - gy0;
+        let mut grid = std::mem::take(&mut self.grid);
+        grid.clear();
+        for gy in gy0..(gy0 + gh) {
+            for gx in gx0..(gx0 + gw) {
+                let p = Vec2f(ox + (gx * CELL) as f32, oy + (gy * CELL) as f32);
+                grid.push(self.light_at(p, blockers));
+            }
+        }
+        for y in y0..y1 {
+            let (gy, fy) = (y / CELL - gy0, (y % CELL) as f32 / CELL as f32);
+            let row = &mut screen.framebuffer[(y * w + x0) * 4..(y * w + x1) * 4];
+            for (x, px) in (x0..x1).zip(row.chunks_exact_mut(4)) {
+                let (gx, fx) = (x / CELL - gx0, (x % CELL) as f32 / CELL as f32);
+                let i = gy * gw + gx;
+                let (a, b, c, d) = (grid[i], grid[i + 1], grid[i + gw], grid[i + gw + 1]);
+                for ch in 0..3 {
+                    let top = a[ch] + (b[ch] - a[ch]) * fx;
+                    let bot = c[ch] + (d[ch] - c[ch]) * fx;
+                    let l = top + (bot - top) * fy;
+                    px[ch] = (px[ch] as f32 * l).round() as u8;
+                }
+            }
+        }
+        self.grid = grid;
+    }
+
+    fn ambient_at(&self, Vec2f(x, y): Vec2f) -> [f32; 3] {
+        let region = self.regions.iter().rev().find(|r| {
+            x >= r.area.x as f32
+                && x < (r.area.x + r.area.w as i32) as f32
+                && y >= r.area.y as f32
+                && y < (r.area.y + r.area.h as i32) as f32
+        });
+        let (top, bottom, t) = match region {
+            Some(r) => (r.top, r.bottom, (y - r.area.y as f32) / r.area.h as f32),
+            None => (self.ambient, self.ambient, 0.0),
+        };
+        let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t) / 255.0;
+        [
+            mix(top.0, bottom.0),
+            mix(top.1, bottom.1),
+            mix(top.2, bottom.2),
+        ]
+    }
+}
+
+// Which solid tile (map, index) is at p, if any
+fn solid_tile(maps: &[&Tilemap], Vec2f(x, y): Vec2f) -> Option<(usize, usize)> {
+    for (m, map) in maps.iter().enumerate() {
+        let tx = ((x - map.position.0) / TILE_SZ as f32).floor();
+        let ty = ((y - map.position.1) / TILE_SZ as f32).floor();
+        if tx < 0.0 || ty < 0.0 || tx as usize >= map.dims.0 || ty as usize >= map.dims.1 {
+            continue;
+        }
+        let i = ty as usize * map.dims.0 + tx as usize;
+        return if map.tileset[map.map[i]].solid {
+            Some((m, i))
+        } else {
+            None
+        };
+    }
+    None
+}
+
+// Is there a solid tile between the light and p?  The tiles at either end
+// don't count, so walls facing a light get lit, and a light stuck in a wall
+// still shines.
+fn blocked(from: Vec2f, to: Vec2f, target: Option<(usize, usize)>, maps: &[&Tilemap]) -> bool {
+    let source = solid_tile(maps, from);
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let steps = ((dx * dx + dy * dy).sqrt() / RAY_STEP) as usize;
+    (0..steps).any(|i| {
+        let t = i as f32 / steps as f32;
+        match solid_tile(maps, Vec2f(from.0 + dx * t, from.1 + dy * t)) {
+            Some(tile) => Some(tile) != target && Some(tile) != source,
+            None => false,
+        }
+    })
+}
@@ -0,0 +1,131 @@
+use crate::screen::{Screen, Transform};
+use crate::texture::Texture;
+use crate::types::{Rect, Vec2f};
+use std::rc::Rc;
+
+// How the edges and the middle fill up the space between the corners
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum SliceFill {
+    Stretch,
+    // Repeat at the original size, cutting off the last copy
+    Tile,
+}
+
+// A panel image cut into a 3x3 grid by its border insets.  The corners
+// always draw at their own size, so the panel can be any size without the
+// border getting stretched out of shape.
+#[derive(Clone)]
+pub struct NineSlice {
+    pub image: Rc<Texture>,
+    // Where the panel is in the image
+    pub frame: Rect,
+    // Border widths as (left, top, right, bottom)
+    pub insets: (u16, u16, u16, u16),
+    pub fill: SliceFill,
+}
+
+impl NineSlice {
+    pub fn new(image: &Rc<Texture>, frame: Rect, insets: (u16, u16, u16, u16)) -> Self {
+        assert!(
+            image.valid_frame(frame),
+            "Nine-slice frame is outside its image"
+        );
+        assert!(
+            insets.0 as u32 + insets.2 as u32 <= frame.w as u32
+                && insets.1 as u32 + insets.3 as u32 <= frame.h as u32,
+            "Nine-slice insets are bigger than its frame"
+        );
+        Self {
+            image: Rc::clone(image),
+            frame,
+            insets,
+            fill: SliceFill::Stretch,
+        }
+    }
+}
+
+pub trait DrawNineSliceExt {
+    fn draw_nine_slice(&mut self, n: &NineSlice, to: Rect);
+}
+
+impl<'fb> DrawNineSliceExt for Screen<'fb> {
+    fn draw_nine_slice(&mut self, n: &NineSlice, to: Rect) {
+        let (l, t, r, b) = n.insets;
+        let f = n.frame;
+        // Columns and rows as (offset, size), first in the image, then on screen
+        let src_cols = [(0, l), (l, f.w - l - r), (f.w - r, r)];
+        let src_rows = [(0, t), (t, f.h - t - b), (f.h - b, b)];
+        let dst_cols = split(to.w, l, r);
+        let dst_rows = split(to.h, t, b);
+        for (i, &(sy, sh)) in src_rows.iter().enumerate() {
+            for (j, &(sx, sw)) in src_cols.iter().enumerate() {
+                let (dy, dh) = dst_rows[i];
+                let (dx, dw) = dst_cols[j];
+                let from = Rect {
+                    x: f.x + sx as i32,
+                    y: f.y + sy as i32,
+                    w: sw,
+                    h: sh,
+                };
+                let dest = Rect {
+                    x: to.x + dx as i32,
+                    y: to.y + dy as i32,
+                    w: dw,
+                    h: dh,
+                };
+                // Corners only ever get squashed, when the panel's too small for them
+                let fill = if i == 1 || j == 1 {
+                    n.fill
+                } else {
+                    SliceFill::Stretch
+                };
+                draw_piece(self, &n.image, from, dest, fill);
+            }
+        }
+    }
+}
+
+// Splits `len` into (offset, size) for the two borders and what's left between.
+// If the borders don't fit they shrink to share the space.
+fn split(len: u16, a: u16, b: u16) -> [(u16, u16); 3] {
+    let (a, b) = if a as u32 + b as u32 > len as u32 {
+        let a = (len as u32 * a as u32 / (a as u32 + b as u32)) as u16;
+        (a, len - a)
+    } else {
+        (a, b)
+    };
+    [(0, a), (a, len - a - b), (len - b, b)]
+}
+
+fn draw_piece(screen: &mut Screen, image: &Texture, from: Rect, to: Rect, fill: SliceFill) {
+    if from.w == 0 || from.h == 0 || to.w == 0 || to.h == 0 {
+        return;
+    }
+    let pos = Vec2f(to.x as f32, to.y as f32);
+    if from.w == to.w && from.h == to.h {
+        screen.bitblt(image, from, pos);
+        return;
+    }
+    match fill {
+        SliceFill::Stretch => {
+            screen.bitblt_transformed(image, from, pos, Transform::new(to.w, to.h))
+        }
+        SliceFill::Tile => {
+            let mut y = 0;
+            while y < to.h {
+                let h = from.h.min(to.h - y);
+                let mut x = 0;
+                while x < to.w {
+                    let w = from.w.min(to.w - x);
+                    screen.bitblt(
+                        image,
+                        Rect { w, h, ..from },
+                        Vec2f((to.x + x as i32) as f32, (to.y + y as i32) as f32),
+                    );
+                    x += w;
+                }
+                y += h;
+            }
+        }
+    }
+}
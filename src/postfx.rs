@@ -0,0 +1,268 @@
+use crate::screen::Screen;
+use crate::types::Rgba;
+
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+pub struct EffectID(pub usize);
+
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+pub enum ColorBlindness {
+    // No red cones
+    Protanopia,
+    // No green cones, the most common kind
+    Deuteranopia,
+    // No blue cones
+    Tritanopia,
+}
+
+// What a pass does to the frame at full strength
+#[derive(PartialEq, Clone, Debug)]
+pub enum Effect {
+    // Solid color
+    Fade(Rgba),
+    // Every other row darkened, like an old CRT
+    Scanlines,
+    // Darker towards the corners
+    Vignette,
+    // Every pixel snapped to the nearest color in the palette
+    Quantize(Vec<Rgba>),
+    Grayscale,
+    ColorBlind(ColorBlindness),
+}
+
+impl Effect {
+    fn process(&self, [r, g, b]: [f32; 3], x: usize, y: usize, w: usize, h: usize) -> [f32; 3] {
+        match self {
+            Effect::Fade(c) => [c.0 as f32, c.1 as f32, c.2 as f32],
+            Effect::Scanlines => {
+                if y % 2 == 1 {
+                    [r * 0.5, g * 0.5, b * 0.5]
+                } else {
+                    [r, g, b]
+                }
+            }
+            Effect::Vignette => {
+                // 0 in the middle, 1 in the corners
+                let dx = (x as f32 + 0.5) / w as f32 * 2.0 - 1.0;
+                let dy = (y as f32 + 0.5) / h as f32 * 2.0 - 1.0;
+                let k = 1.0 - (dx * dx + dy * dy) / 2.0;
+                [r * k, g * k, b * k]
+            }
+            Effect::Quantize(palette) => {
+                let dist = |c: &&Rgba| {
+                    let (dr, dg, db) = (c.0 as f32 - r, c.1 as f32 - g, c.2 as f32 - b);
+                    dr * dr + dg * dg + db * db
+                };
+                match palette
+                    .iter()
+                    .min_by(|a, b| dist(a).partial_cmp(&dist(b)).unwrap())
+                {
+                    Some(c) => [c.0 as f32, c.1 as f32, c.2 as f32],
+                    None => [r, g, b],
+                }
+            }
+            Effect::Grayscale => {
+                let l = 0.299 * r + 0.587 * g + 0.114 * b;
+                [l, l, l]
+            }
+            Effect::ColorBlind(kind) => {
+                // Machado et al. 2009 at full severity.  They're meant for
+                // linear RGB; straight on sRGB is close enough to check that
+                // things stay readable.
+                let m = match kind {
+                    ColorBlindness::Protanopia => [
+                        [0.152286, 1.052583, -0.204868],
+                        [0.114503, 0.786281, 0.099216],
+                        [-0.003882, -0.048116, 1.051998],
+                    ],
+                    ColorBlindness::Deuteranopia => [
+                        [0.367322, 0.860646, -0.227968],
+                        [0.280085, 0.672501, 0.047413],
+                        [-0.011820, 0.042940, 0.968881],
+                    ],
+                    ColorBlindness::Tritanopia => [
+                        [1.255528, -0.076749, -0.178779],
+                        [-0.078411, 0.930809, 0.147602],
+                        [0.004733, 0.691367, 0.303900],
+                    ],
+                };
+                [
+                    m[0][0] * r + m[0][1] * g + m[0][2] * b,
+                    m[1][0] * r + m[1][1] * g + m[1][2] * b,
+                    m[2][0] * r + m[2][1] * g + m[2][2] * b,
+                ]
+            }
+        }
+    }
+}
+
+// Moves a strength from one value to another over some ticks
+#[derive(PartialEq, Clone, Copy, Debug)]
+struct Tween {
+    from: f32,
+    to: f32,
+    ticks: usize,
+    elapsed: usize,
+}
+
+impl Tween {
+    fn value(&self) -> f32 {
+        if self.elapsed >= self.ticks {
+            return self.to;
+        }
+        self.from + (self.to - self.from) * self.elapsed as f32 / self.ticks as f32
+    }
+}
+
+struct Pass {
+    id: EffectID,
+    effect: Effect,
+    // 0.0 leaves the frame alone, 1.0 is the full effect
+    strength: f32,
+    tween: Option<Tween>,
+}
+
+impl Pass {
+    fn animate(&mut self, to: f32, ticks: usize) {
+        self.tween = Some(Tween {
+            from: self.strength,
+            to,
+            ticks,
+            elapsed: 0,
+        });
+        if ticks == 0 {
+            self.update();
+        }
+    }
+
+    fn update(&mut self) {
+        if let Some(t) = self.tween.as_mut() {
+            t.elapsed += 1;
+            self.strength = t.value();
+            if t.elapsed >= t.ticks {
+                self.tween = None;
+            }
+        }
+    }
+}
+
+// A chain of effects run over the finished frame, in the order they were
+// pushed, with a fade on top of everything.  Call `update` once per
+// simulation tick and `apply` after drawing, before the frame is shown.
+pub struct PostFx {
+    passes: Vec<Pass>,
+    fade: Pass,
+    next_id: usize,
+}
+
+impl Default for PostFx {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PostFx {
+    pub fn new() -> Self {
+        Self {
+            passes: vec![],
+            fade: Pass {
+                id: EffectID(usize::MAX),
+                effect: Effect::Fade(Rgba(0, 0, 0, 255)),
+                strength: 0.0,
+                tween: None,
+            },
+            next_id: 0,
+        }
+    }
+
+    pub fn push(&mut self, effect: Effect, strength: f32) -> EffectID {
+        let id = EffectID(self.next_id);
+        self.next_id += 1;
+        self.passes.push(Pass {
+            id,
+            effect,
+            strength,
+            tween: None,
+        });
+        id
+    }
+
+    pub fn remove(&mut self, id: EffectID) {
+        self.passes.retain(|p| p.id != id);
+    }
+
+    pub fn clear(&mut self) {
+        self.passes.clear();
+    }
+
+    pub fn strength(&self, id: EffectID) -> Option<f32> {
+        self.pass(id).map(|p| p.strength)
+    }
+
+    // Jump straight to a strength, cancelling any animation
+    pub fn set_strength(&mut self, id: EffectID, strength: f32) {
+        if let Some(p) = self.pass_mut(id) {
+            p.strength = strength;
+            p.tween = None;
+        }
+    }
+
+    // Go from the current strength to `to` over `ticks` ticks
+    pub fn animate(&mut self, id: EffectID, to: f32, ticks: usize) {
+        if let Some(p) = self.pass_mut(id) {
+            p.animate(to, ticks);
+        }
+    }
+
+    pub fn is_animating(&self, id: EffectID) -> bool {
+        self.pass(id).is_some_and(|p| p.tween.is_some())
+    }
+
+    // Fade the whole frame to color
+    pub fn fade_out(&mut self, color: Rgba, ticks: usize) {
+        self.fade.effect = Effect::Fade(color);
+        self.fade.animate(1.0, ticks);
+    }
+
+    // And back from whatever color it faded to
+    pub fn fade_in(&mut self, ticks: usize) {
+        self.fade.animate(0.0, ticks);
+    }
+
+    pub fn fading(&self) -> bool {
+        self.fade.tween.is_some()
+    }
+
+    pub fn update(&mut self) {
+        for p in self.passes.iter_mut() {
+            p.update();
+        }
+        self.fade.update();
+    }
+
+    // Runs over the whole framebuffer, ignoring any clip
+    pub fn apply(&self, screen: &mut Screen) {
+        let (w, h) = screen.size();
+        for p in self.passes.iter().chain(std::iter::once(&self.fade)) {
+            let s = p.strength.clamp(0.0, 1.0);
+            if s == 0.0 {
+                continue;
+            }
+            screen.mark_all_dirty();
+            for (i, px) in screen.framebuffer.chunks_exact_mut(4).enumerate() {
+                let rgb = [px[0] as f32, px[1] as f32, px[2] as f32];
+                let out = p.effect.process(rgb, i % w, i / w, w, h);
+                for c in 0..3 {
+                    px[c] = (rgb[c] + (out[c] - rgb[c]) * s).round().clamp(0.0, 255.0) as u8;
+                }
+            }
+        }
+    }
+
+    fn pass(&self, id: EffectID) -> Option<&Pass> {
+        self.passes.iter().find(|p| p.id == id)
+    }
+
+    fn pass_mut(&mut self, id: EffectID) -> Option<&mut Pass> {
+        self.passes.iter_mut().find(|p| p.id == id)
+    }
+}
@@ -0,0 +1,96 @@
+use crate::screen::Screen;
+use crate::sprite::{DrawSpriteExt, Sprite};
+use crate::text::{DrawTextExt, Text};
+use crate::texture::Texture;
+use crate::tiles::Tilemap;
+use crate::types::{Rect, Rgba, Vec2f};
+use std::rc::Rc;
+
+// Anything the queue knows how to draw
+pub enum DrawCommand {
+    Blit {
+        image: Rc<Texture>,
+        from: Rect,
+        to: Vec2f,
+    },
+    Rect {
+        rect: Rect,
+        color: Rgba,
+    },
+    Sprite(Sprite),
+    Text(Text),
+    Tilemap(Rc<Tilemap>),
+}
+
+struct Queued {
+    layer: i32,
+    key: f32,
+    command: DrawCommand,
+}
+
+// Collects draw commands during a frame and draws them back-to-front:
+// lower layers first, then lower sort keys within a layer, then in the
+// order they were pushed.
+#[derive(Default)]
+pub struct RenderQueue {
+    commands: Vec<Queued>,
+}
+
+impl RenderQueue {
+    pub fn new() -> Self {
+        Self { commands: vec![] }
+    }
+
+    pub fn push(&mut self, layer: i32, key: f32, command: DrawCommand) {
+        self.commands.push(Queued {
+            layer,
+            key,
+            command,
+        });
+    }
+
+    // Sprites sort by their feet, so whoever is lower down the screen is in front
+    pub fn push_sprite(&mut self, layer: i32, s: &Sprite) {
+        let key = s.rect.y + s.rect.h as f32;
+        self.push(layer, key, DrawCommand::Sprite(s.clone()));
+    }
+
+    pub fn push_tilemap(&mut self, layer: i32, map: &Rc<Tilemap>) {
+        self.push(layer, 0.0, DrawCommand::Tilemap(Rc::clone(map)));
+    }
+
+    pub fn push_text(&mut self, layer: i32, t: &Text) {
+        self.push(layer, t.pos.1, DrawCommand::Text(t.clone()));
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.commands.clear();
+    }
+
+    // Sort and draw everything, leaving the queue empty for the next frame
+    pub fn flush(&mut self, screen: &mut Screen) {
+        // sort_by is stable, so ties keep their push order.  total_cmp puts
+        // a NaN key (say, from a sprite that flew off to infinity) last
+        // instead of confusing the sort.
+        self.commands
+            .sort_by(|a, b| a.layer.cmp(&b.layer).then(a.key.total_cmp(&b.key)));
+        for q in self.commands.iter_mut() {
+            match &mut q.command {
+                DrawCommand::Blit { image, from, to } => screen.bitblt(image, *from, *to),
+                DrawCommand::Rect { rect, color } => screen.rect(*rect, *color),
+                DrawCommand::Sprite(s) => screen.draw_sprite(s),
+                DrawCommand::Text(t) => screen.draw_text(t),
+                DrawCommand::Tilemap(map) => map.draw(screen),
+            }
+        }
+        self.commands.clear();
+    }
+}
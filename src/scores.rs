@@ -1,3 +1,4 @@
+use crate::error::{Error, Result};
 use serde::Deserialize;
 use serde::Serialize;
 use std::cmp::Reverse;
@@ -10,26 +11,40 @@ pub struct Score {
     pub value: i16,
 }
 
+#[derive(Default)]
 pub struct Scores {
     pub scores: Vec<Score>,
 }
 
 impl Scores {
-    pub fn new(path: &str) -> Self {
+    pub fn new(path: &str) -> Result<Self> {
         Scores::load(path)
     }
 
-    pub fn load(path: &str) -> Self {
-        let file = File::open(path).unwrap();
+    pub fn load(path: &str) -> Result<Self> {
+        let file = File::open(path).map_err(|source| Error::Io {
+            path: path.into(),
+            source,
+        })?;
         let reader = BufReader::new(file);
-        let scores: Vec<Score> = serde_json::from_reader(reader).unwrap();
-        Self { scores }
+        let scores: Vec<Score> = serde_json::from_reader(reader).map_err(|source| Error::Json {
+            path: path.into(),
+            source,
+        })?;
+        Ok(Self { scores })
     }
 
-    pub fn save(&self, path: &str) {
-        let j = serde_json::to_string(&self.scores).unwrap();
-        let mut f = File::create(path).unwrap();
-        f.write_all(&j.as_bytes()).unwrap();
+    pub fn save(&self, path: &str) -> Result<()> {
+        let j = serde_json::to_string(&self.scores).map_err(|source| Error::Json {
+            path: path.into(),
+            source,
+        })?;
+        let io = |source| Error::Io {
+            path: path.into(),
+            source,
+        };
+        let mut f = File::create(path).map_err(io)?;
+        f.write_all(&j.as_bytes()).map_err(io)
     }
 
     pub fn sort(&mut self) {
@@ -1,12 +1,90 @@
 // We can pull in definitions from elsewhere in the crate!
+use crate::dirty::DirtyRegions;
 use crate::texture::Texture;
 use crate::types::{Rect, Rgba, Vec2f};
+
+// How bitblt_transformed should place its source rect
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Transform {
+    // Destination size in pixels; the source rect is stretched to fit
+    pub size: (u16, u16),
+    // Clockwise rotation in radians (y points down)
+    pub angle: f32,
+    // What we rotate around, relative to the destination's top left corner
+    pub pivot: Vec2f,
+    pub flip_x: bool,
+    pub flip_y: bool,
+}
+
+impl Transform {
+    // Unrotated and unflipped, pivoting around the middle
+    pub fn new(w: u16, h: u16) -> Self {
+        Self {
+            size: (w, h),
+            angle: 0.0,
+            pivot: Vec2f(w as f32 / 2.0, h as f32 / 2.0),
+            flip_x: false,
+            flip_y: false,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+pub enum BlendMode {
+    // Regular alpha compositing, like bitblt
+    Over,
+    // Brightens; good for glows and flashes
+    Add,
+    // Darkens; good for shadows
+    Multiply,
+    // Brightens more gently than Add
+    Screen,
+}
+
+// Color modulation for bitblt_blend
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Blend {
+    // Multiplies the source color; its alpha fades the source too
+    pub tint: Rgba,
+    // 0.0 is invisible, 1.0 is as-is
+    pub opacity: f32,
+    pub mode: BlendMode,
+}
+
+impl Blend {
+    // No tint and fully opaque
+    pub fn new(mode: BlendMode) -> Self {
+        Self {
+            tint: Rgba(255, 255, 255, 255),
+            opacity: 1.0,
+            mode,
+        }
+    }
+    // Since the source is premultiplied, fading it means scaling every
+    // channel by the same alpha factor, and tinting scales the colors on top.
+    fn modulation(&self) -> [f32; 4] {
+        let a = (self.tint.3 as f32 / 255.0) * self.opacity.clamp(0.0, 1.0);
+        [
+            self.tint.0 as f32 / 255.0 * a,
+            self.tint.1 as f32 / 255.0 * a,
+            self.tint.2 as f32 / 255.0 * a,
+            a,
+        ]
+    }
+}
+
 pub struct Screen<'fb> {
     pub framebuffer: &'fb mut [u8],
     width: usize,
     height: usize,
     depth: usize,
     position: Vec2f,
+    // Drawing only touches pixels inside clip, which is in framebuffer coordinates
+    clip: Rect,
+    // Saved (clip, position) pairs for pop
+    stack: Vec<(Rect, Vec2f)>,
+    // If we're tracking, everything drawn gets marked here
+    dirty: Option<DirtyRegions>,
 }
 impl<'fb> Screen<'fb> {
     // Call =wrap= every frame; that means the camera position will need to be stored in the game state
@@ -23,38 +101,146 @@ impl<'fb> Screen<'fb> {
             height,
             depth,
             position,
+            clip: Rect {
+                x: 0,
+                y: 0,
+                w: width as u16,
+                h: height as u16,
+            },
+            stack: vec![],
+            dirty: None,
         }
     }
     pub fn size(&self) -> (usize, usize) {
         (self.width, self.height)
     }
+    // The world point at the framebuffer's top left
+    pub fn position(&self) -> Vec2f {
+        self.position
+    }
+    // The part of the world we can currently draw into
     pub fn bounds(&self) -> Rect {
         Rect {
-            x: self.position.0 as i32,
-            y: self.position.1 as i32,
-            w: self.width as u16,
-            h: self.height as u16,
+            x: self.clip.x + self.position.0 as i32,
+            y: self.clip.y + self.position.1 as i32,
+            w: self.clip.w,
+            h: self.clip.h,
         }
     }
+    // Only draw inside r (in world coordinates, like rect) until the matching pop.
+    // Clips nest, so this can only ever shrink the drawable area.
+    pub fn push_clip(&mut self, r: Rect) {
+        self.stack.push((self.clip, self.position));
+        let r = Rect {
+            x: r.x - self.position.0 as i32,
+            y: r.y - self.position.1 as i32,
+            ..r
+        };
+        self.clip = intersect(self.clip, r);
+    }
+    // Draw into `area` of the framebuffer (in framebuffer coordinates) as
+    // though it were its own screen looking at the world from `camera`.
+    // Handy for split-screen or a minimap in the corner.
+    pub fn push_viewport(&mut self, area: Rect, camera: Vec2f) {
+        self.stack.push((self.clip, self.position));
+        self.clip = intersect(self.clip, area);
+        self.position = Vec2f(camera.0 - area.x as f32, camera.1 - area.y as f32);
+    }
+    // Undo the most recent push_clip or push_viewport
+    pub fn pop(&mut self) {
+        let (clip, position) = self.stack.pop().expect("pop without a matching push");
+        self.clip = clip;
+        self.position = position;
+    }
+    // Start marking everything drawn in `dirty`, which should be the framebuffer's size
+    pub fn track(&mut self, dirty: DirtyRegions) {
+        self.dirty = Some(dirty);
+    }
+    // Stop tracking and hand back what got drawn
+    pub fn untrack(&mut self) -> Option<DirtyRegions> {
+        self.dirty.take()
+    }
+    // For anything that writes to the framebuffer directly instead of
+    // through the drawing calls.  r is in world coordinates, like rect.
+    pub fn mark_dirty(&mut self, r: Rect) {
+        let r = intersect(
+            self.clip,
+            Rect {
+                x: r.x - self.position.0 as i32,
+                y: r.y - self.position.1 as i32,
+                ..r
+            },
+        );
+        self.touch(r.x, r.y, r.x + r.w as i32, r.y + r.h as i32);
+    }
+    // For effects that go over the whole framebuffer regardless of the clip
+    pub fn mark_all_dirty(&mut self) {
+        self.touch(0, 0, self.width as i32, self.height as i32);
+    }
+    // Marks a span of the framebuffer as drawn, if we're tracking
+    #[inline(always)]
+    fn touch(&mut self, x0: i32, y0: i32, x1: i32, y1: i32) {
+        if let Some(d) = self.dirty.as_mut() {
+            if x0 < x1 && y0 < y1 {
+                d.mark(Rect {
+                    x: x0,
+                    y: y0,
+                    w: (x1 - x0) as u16,
+                    h: (y1 - y0) as u16,
+                });
+            }
+        }
+    }
+    // Clip edges as (x0, y0, x1, y1), half-open
+    #[inline(always)]
+    fn clip_edges(&self) -> (i32, i32, i32, i32) {
+        (
+            self.clip.x,
+            self.clip.y,
+            self.clip.x + self.clip.w as i32,
+            self.clip.y + self.clip.h as i32,
+        )
+    }
     // Our old, slow friend draw_at, now with super scrolling powers!
     #[inline(always)]
-    pub fn draw_at(&mut self, col: Rgba, Vec2f(x, y): Vec2f) {
+    pub fn draw_at(&mut self, col: Rgba, pos: Vec2f) {
+        self.plot(col, pos, false);
+    }
+    // Like draw_at, but composites col over what's already there
+    #[inline(always)]
+    pub fn draw_at_blend(&mut self, col: Rgba, pos: Vec2f) {
+        self.plot(col, pos, true);
+    }
+    #[inline(always)]
+    fn plot(&mut self, col: Rgba, Vec2f(x, y): Vec2f, blend: bool) {
         let x = (x - self.position.0) as i32;
         let y = (y - self.position.1) as i32;
         // The rest is about the same
-        if x < 0 || (self.width as i32) <= x || y < 0 || (self.height as i32) <= y {
+        let (cx0, cy0, cx1, cy1) = self.clip_edges();
+        if x < cx0 || cx1 <= x || y < cy0 || cy1 <= y {
             return;
         }
         assert_eq!(self.depth, 4);
+        self.touch(x, y, x + 1, y + 1);
         // Now x and y are within framebuffer bounds so go ahead and draw
-        let c = [col.0, col.1, col.2, col.3];
         let idx = y * self.width as i32 * self.depth as i32 + x * self.depth as i32;
         assert!(idx >= 0);
         let idx = idx as usize;
-        self.framebuffer[idx..(idx + self.depth)].copy_from_slice(&c);
+        let px = &mut self.framebuffer[idx..(idx + self.depth)];
+        if blend {
+            composite(px, &premultiply(col));
+        } else {
+            px.copy_from_slice(&[col.0, col.1, col.2, col.3]);
+        }
     }
-    // Clear's the same...
+    // Clear's the same... unless we're clipped, then it just fills the clip
     pub fn clear(&mut self, col: Rgba) {
+        if !self.stack.is_empty() {
+            let b = self.bounds();
+            self.fill_rect(b, col, false);
+            return;
+        }
+        self.mark_all_dirty();
         let c = [col.0, col.1, col.2, col.3];
         for px in self.framebuffer.chunks_exact_mut(4) {
             px.copy_from_slice(&c);
@@ -62,29 +248,56 @@ impl<'fb> Screen<'fb> {
     }
     // Rect needs a translation to start
     pub fn rect(&mut self, r: Rect, col: Rgba) {
-        let c = [col.0, col.1, col.2, col.3];
+        self.fill_rect(r, col, false);
+    }
+    // Translucent rects (HUD panels, fades) blend over what's underneath
+    pub fn rect_blend(&mut self, r: Rect, col: Rgba) {
+        self.fill_rect(r, col, true);
+    }
+    fn fill_rect(&mut self, r: Rect, col: Rgba, blend: bool) {
+        let c = if blend {
+            premultiply(col)
+        } else {
+            [col.0, col.1, col.2, col.3]
+        };
         // Here's the translation
         let r = Rect {
             x: r.x - self.position.0 as i32,
             y: r.y - self.position.1 as i32,
             ..r
         };
-        // And the rest is just the same
-        let x0 = r.x.max(0).min(self.width as i32) as usize;
-        let x1 = (r.x + r.w as i32).max(0).min(self.width as i32) as usize;
-        let y0 = r.y.max(0).min(self.height as i32) as usize;
-        let y1 = (r.y + r.h as i32).max(0).min(self.height as i32) as usize;
+        // And the rest is just the same, but against the clip instead of the framebuffer
+        let (cx0, cy0, cx1, cy1) = self.clip_edges();
+        let x0 = r.x.max(cx0).min(cx1) as usize;
+        let x1 = (r.x + r.w as i32).max(cx0).min(cx1) as usize;
+        let y0 = r.y.max(cy0).min(cy1) as usize;
+        let y1 = (r.y + r.h as i32).max(cy0).min(cy1) as usize;
+        self.touch(x0 as i32, y0 as i32, x1 as i32, y1 as i32);
         let depth = self.depth;
         let pitch = self.width * depth;
         for row in self.framebuffer[(y0 * pitch)..(y1 * pitch)].chunks_exact_mut(pitch) {
             for p in row[(x0 * depth)..(x1 * depth)].chunks_exact_mut(depth) {
-                p.copy_from_slice(&c);
+                if blend {
+                    composite(p, &c);
+                } else {
+                    p.copy_from_slice(&c);
+                }
             }
         }
     }
     // Ditto line
-    pub fn line(&mut self, Vec2f(x0, y0): Vec2f, Vec2f(x1, y1): Vec2f, col: Rgba) {
-        let col = [col.0, col.1, col.2, col.3];
+    pub fn line(&mut self, from: Vec2f, to: Vec2f, col: Rgba) {
+        self.draw_line(from, to, col, false);
+    }
+    pub fn line_blend(&mut self, from: Vec2f, to: Vec2f, col: Rgba) {
+        self.draw_line(from, to, col, true);
+    }
+    fn draw_line(&mut self, Vec2f(x0, y0): Vec2f, Vec2f(x1, y1): Vec2f, col: Rgba, blend: bool) {
+        let col = if blend {
+            premultiply(col)
+        } else {
+            [col.0, col.1, col.2, col.3]
+        };
         // translate translate
         let x0 = (x0 - self.position.0) as i32;
         let y0 = (y0 - self.position.1) as i32;
@@ -99,15 +312,25 @@ impl<'fb> Screen<'fb> {
         let dy = -(y1 - y0).abs();
         let sy = if y0 < y1 { 1 } else { -1 };
         let mut err = dx + dy;
-        let width = self.width as i32;
-        let height = self.height as i32;
+        let (cx0, cy0, cx1, cy1) = self.clip_edges();
+        self.touch(
+            x0.min(x1).max(cx0),
+            y0.min(y1).max(cy0),
+            (x0.max(x1) + 1).min(cx1),
+            (y0.max(y1) + 1).min(cy1),
+        );
         while x != x1 || y != y1 {
-            if 0 <= x && x < width && 0 <= y && y < height {
+            if cx0 <= x && x < cx1 && cy0 <= y && y < cy1 {
                 // TODO this bounds check could in theory be avoided with
                 // the unsafe get_unchecked, but maybe better not...
-                self.framebuffer[(y as usize * self.width * self.depth + x as usize * self.depth)
-                    ..(y as usize * self.width * self.depth + (x as usize + 1) * self.depth)]
-                    .copy_from_slice(&col);
+                let px = &mut self.framebuffer[(y as usize * self.width * self.depth
+                    + x as usize * self.depth)
+                    ..(y as usize * self.width * self.depth + (x as usize + 1) * self.depth)];
+                if blend {
+                    composite(px, &col);
+                } else {
+                    px.copy_from_slice(&col);
+                }
                 // We couldn't just clamp x0/y0 and x1/y1 into bounds, because then
                 // we might change the slope of the line.
             }
@@ -123,8 +346,232 @@ impl<'fb> Screen<'fb> {
         }
     }
 
+    // The shapes below all blend, so translucent colors work; an opaque
+    // color just overwrites.  A pixel is covered when its center is inside
+    // the shape, and outlines grow inwards from the shape's edge by `width`.
+    pub fn fill_circle(&mut self, center: Vec2f, radius: f32, col: Rgba) {
+        self.fill_ellipse(center, Vec2f(radius, radius), col);
+    }
+    pub fn circle(&mut self, center: Vec2f, radius: f32, width: f32, col: Rgba) {
+        self.ellipse(center, Vec2f(radius, radius), width, col);
+    }
+    pub fn fill_ellipse(&mut self, center: Vec2f, radii: Vec2f, col: Rgba) {
+        let c = self.local(center);
+        self.fill_between(
+            c.1 - radii.1,
+            c.1 + radii.1,
+            |y| ellipse_span(c, radii, y),
+            |_| None,
+            col,
+        );
+    }
+    pub fn ellipse(&mut self, center: Vec2f, radii: Vec2f, width: f32, col: Rgba) {
+        let c = self.local(center);
+        let inner = Vec2f(radii.0 - width, radii.1 - width);
+        self.fill_between(
+            c.1 - radii.1,
+            c.1 + radii.1,
+            |y| ellipse_span(c, radii, y),
+            |y| ellipse_span(c, inner, y),
+            col,
+        );
+    }
+    pub fn fill_triangle(&mut self, a: Vec2f, b: Vec2f, c: Vec2f, col: Rgba) {
+        self.fill_polygon(&[a, b, c], col);
+    }
+    // The points can go either way around, but the polygon has to be convex
+    pub fn fill_polygon(&mut self, points: &[Vec2f], col: Rgba) {
+        if points.len() < 3 {
+            return;
+        }
+        let pts: Vec<Vec2f> = points.iter().map(|&p| self.local(p)).collect();
+        let top = pts.iter().map(|p| p.1).fold(f32::INFINITY, f32::min);
+        let bottom = pts.iter().map(|p| p.1).fold(f32::NEG_INFINITY, f32::max);
+        self.fill_between(top, bottom, |y| polygon_span(&pts, y), |_| None, col);
+    }
+    // A line `width` pixels thick, with square ends at from and to
+    pub fn thick_line(&mut self, from: Vec2f, to: Vec2f, width: f32, col: Rgba) {
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        let len = (dx * dx + dy * dy).sqrt();
+        if len == 0.0 || width <= 0.0 {
+            return;
+        }
+        // Half the width, at right angles to the line
+        let (nx, ny) = (-dy / len * width / 2.0, dx / len * width / 2.0);
+        self.fill_polygon(
+            &[
+                Vec2f(from.0 + nx, from.1 + ny),
+                Vec2f(to.0 + nx, to.1 + ny),
+                Vec2f(to.0 - nx, to.1 - ny),
+                Vec2f(from.0 - nx, from.1 - ny),
+            ],
+            col,
+        );
+    }
+    // Radius gets clamped to fit, so a big one makes a pill shape
+    pub fn fill_rounded_rect(&mut self, r: Rect, radius: f32, col: Rgba) {
+        let (x, y) = (r.x as f32 - self.position.0, r.y as f32 - self.position.1);
+        let (w, h) = (r.w as f32, r.h as f32);
+        self.fill_between(
+            y,
+            y + h,
+            |yc| rounded_span(x, y, w, h, radius, yc),
+            |_| None,
+            col,
+        );
+    }
+    pub fn rounded_rect(&mut self, r: Rect, radius: f32, width: f32, col: Rgba) {
+        let (x, y) = (r.x as f32 - self.position.0, r.y as f32 - self.position.1);
+        let (w, h) = (r.w as f32, r.h as f32);
+        let (iw, ih) = (w - 2.0 * width, h - 2.0 * width);
+        // The inner corners curve around the same centers as the outer ones
+        let inner_radius = (radius.min(w / 2.0).min(h / 2.0) - width).max(0.0);
+        self.fill_between(
+            y,
+            y + h,
+            |yc| rounded_span(x, y, w, h, radius, yc),
+            |yc| rounded_span(x + width, y + width, iw, ih, inner_radius, yc),
+            col,
+        );
+    }
+
+    // Gradients fill r, blending like the shapes do.  Stops are (offset, color)
+    // pairs with offsets from 0.0 to 1.0 in increasing order; before the first
+    // stop and after the last the color stays flat.
+    // A linear gradient runs from `from` (offset 0) to `to` (offset 1).
+    pub fn linear_gradient(&mut self, r: Rect, from: Vec2f, to: Vec2f, stops: &[(f32, Rgba)]) {
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        let len2 = dx * dx + dy * dy;
+        self.fill_gradient(r, stops, |x, y| {
+            if len2 == 0.0 {
+                0.0
+            } else {
+                ((x - from.0) * dx + (y - from.1) * dy) / len2
+            }
+        });
+    }
+    // Offset 0 at center, offset 1 out at radius
+    pub fn radial_gradient(&mut self, r: Rect, center: Vec2f, radius: f32, stops: &[(f32, Rgba)]) {
+        self.fill_gradient(r, stops, |x, y| {
+            let (dx, dy) = (x - center.0, y - center.1);
+            (dx * dx + dy * dy).sqrt() / radius
+        });
+    }
+    // `offset` gets the world position of each pixel's center
+    fn fill_gradient<F: Fn(f32, f32) -> f32>(&mut self, r: Rect, stops: &[(f32, Rgba)], offset: F) {
+        if stops.is_empty() {
+            return;
+        }
+        // Mixing premultiplied colors keeps fades to transparent from going muddy
+        let stops: Vec<(f32, [f32; 4])> = stops
+            .iter()
+            .map(|&(t, col)| {
+                let c = premultiply(col);
+                (t, [c[0] as f32, c[1] as f32, c[2] as f32, c[3] as f32])
+            })
+            .collect();
+        let local = Rect {
+            x: r.x - self.position.0 as i32,
+            y: r.y - self.position.1 as i32,
+            ..r
+        };
+        let (cx0, cy0, cx1, cy1) = self.clip_edges();
+        let x0 = local.x.max(cx0).min(cx1);
+        let x1 = (local.x + local.w as i32).max(cx0).min(cx1);
+        let y0 = local.y.max(cy0).min(cy1);
+        let y1 = (local.y + local.h as i32).max(cy0).min(cy1);
+        self.touch(x0, y0, x1, y1);
+        let depth = self.depth;
+        let pitch = self.width * depth;
+        for y in y0..y1 {
+            let wy = y as f32 + 0.5 + self.position.1;
+            let row = &mut self.framebuffer[(y as usize * pitch)..((y as usize + 1) * pitch)];
+            for x in x0..x1 {
+                let wx = x as f32 + 0.5 + self.position.0;
+                let c = gradient_at(&stops, offset(wx, wy));
+                composite(
+                    &mut row[(x as usize * depth)..((x as usize + 1) * depth)],
+                    &c,
+                );
+            }
+        }
+    }
+    // Shapes come in in world coordinates; this moves them into the framebuffer's
+    fn local(&self, Vec2f(x, y): Vec2f) -> Vec2f {
+        Vec2f(x - self.position.0, y - self.position.1)
+    }
+    // Fills every row between top and bottom (in framebuffer coordinates)
+    // with what `outer` covers and `inner` doesn't.  Both get the y of a row's
+    // pixel centers and return the x range the shape covers there, if any.
+    fn fill_between<O, I>(&mut self, top: f32, bottom: f32, outer: O, inner: I, col: Rgba)
+    where
+        O: Fn(f32) -> Option<(f32, f32)>,
+        I: Fn(f32) -> Option<(f32, f32)>,
+    {
+        let c = premultiply(col);
+        let (_, cy0, _, cy1) = self.clip_edges();
+        let y0 = ((top - 0.5).ceil() as i32).max(cy0);
+        let y1 = ((bottom - 0.5).ceil() as i32).min(cy1);
+        for y in y0..y1 {
+            let yc = y as f32 + 0.5;
+            if let Some((x0, x1)) = outer(yc) {
+                match inner(yc) {
+                    Some((ix0, ix1)) if ix0 < ix1 => {
+                        self.span(y, x0, ix0.min(x1), &c);
+                        self.span(y, ix1.max(x0), x1, &c);
+                    }
+                    _ => self.span(y, x0, x1, &c),
+                }
+            }
+        }
+    }
+    // Blends c over the pixels in row y whose centers fall in x0..x1
+    fn span(&mut self, y: i32, x0: f32, x1: f32, c: &[u8; 4]) {
+        let (cx0, _, cx1, _) = self.clip_edges();
+        let x0 = ((x0 - 0.5).ceil() as i32).max(cx0);
+        let x1 = ((x1 - 0.5).ceil() as i32).min(cx1);
+        if x0 >= x1 {
+            return;
+        }
+        self.touch(x0, y, x1, y + 1);
+        let depth = self.depth;
+        let row = y as usize * self.width * depth;
+        let px = &mut self.framebuffer[(row + x0 as usize * depth)..(row + x1 as usize * depth)];
+        for p in px.chunks_exact_mut(depth) {
+            composite(p, c);
+        }
+    }
+
     // Bitblt too begins with a translation
-    pub fn bitblt(&mut self, src: &Texture, from: Rect, Vec2f(to_x, to_y): Vec2f) {
+    pub fn bitblt(&mut self, src: &Texture, from: Rect, to: Vec2f) {
+        self.blit_with(src, from, to, composite_row);
+    }
+
+    // Bitblt with the source tinted and faded, then combined with the
+    // destination using the given blend mode.
+    pub fn bitblt_blend(&mut self, src: &Texture, from: Rect, to: Vec2f, blend: Blend) {
+        if blend == Blend::new(BlendMode::Over) {
+            self.bitblt(src, from, to);
+            return;
+        }
+        let m = blend.modulation();
+        self.blit_with(src, from, to, |to, from| {
+            for (to, from) in to.chunks_exact_mut(4).zip(from.chunks_exact(4)) {
+                composite_mode(to, &modulate(from, m), blend.mode);
+            }
+        });
+    }
+
+    // Clipping and row-walking shared by all the axis-aligned blits.
+    // Each overlapping pair of (destination, source) row spans goes to `f`.
+    #[inline(always)]
+    fn blit_with<F: FnMut(&mut [u8], &[u8])>(
+        &mut self,
+        src: &Texture,
+        from: Rect,
+        Vec2f(to_x, to_y): Vec2f,
+        mut f: F,
+    ) {
         let (tw, th) = src.size();
         assert!(0 <= from.x);
         assert!(from.x < tw as i32);
@@ -132,10 +579,11 @@ impl<'fb> Screen<'fb> {
         assert!(from.y < th as i32);
         let to_x = (to_x - self.position.0) as i32;
         let to_y = (to_y - self.position.1) as i32;
-        if (to_x + from.w as i32) < 0
-            || (self.width as i32) <= to_x
-            || (to_y + from.h as i32) < 0
-            || (self.height as i32) <= to_y
+        let (cx0, cy0, cx1, cy1) = self.clip_edges();
+        if (to_x + from.w as i32) < cx0
+            || cx1 <= to_x
+            || (to_y + from.h as i32) < cy0
+            || cy1 <= to_y
         {
             return;
         }
@@ -146,10 +594,11 @@ impl<'fb> Screen<'fb> {
         // All this rigmarole is just to avoid bounds checks on each pixel of the blit.
         // We want to calculate which row/col of the src image to start at and which to end at.
         // This way there's no need to even check for out of bounds draws.
-        let y_skip = to_y.max(0) - to_y;
-        let x_skip = to_x.max(0) - to_x;
-        let y_count = (to_y + from.h as i32).min(self.height as i32) - to_y;
-        let x_count = (to_x + from.w as i32).min(self.width as i32) - to_x;
+        let y_skip = to_y.max(cy0) - to_y;
+        let x_skip = to_x.max(cx0) - to_x;
+        let y_count = (to_y + from.h as i32).min(cy1) - to_y;
+        let x_count = (to_x + from.w as i32).min(cx1) - to_x;
+        self.touch(to_x + x_skip, to_y + y_skip, to_x + x_count, to_y + y_count);
         let src_buf = src.buffer();
         for (row_a, row_b) in src_buf[(src_pitch * ((from.y + y_skip) as usize))
             ..(src_pitch * ((from.y + y_count) as usize))]
@@ -160,21 +609,275 @@ impl<'fb> Screen<'fb> {
                     .chunks_exact_mut(dst_pitch),
             )
         {
-            let to_cols = row_b
-                [(depth * (to_x + x_skip) as usize)..(depth * (to_x + x_count) as usize)]
-                .chunks_exact_mut(depth);
-            let from_cols = row_a
-                [(depth * (from.x + x_skip) as usize)..(depth * (from.x + x_count) as usize)]
-                .chunks_exact(depth);
-            // Composite over, assume premultiplied rgba8888
-            for (to, from) in to_cols.zip(from_cols) {
-                let ta = to[3] as f32 / 255.0;
-                let fa = from[3] as f32 / 255.0;
-                for i in 0..3 {
-                    to[i] = from[i].saturating_add((to[i] as f32 * (1.0 - fa)).round() as u8);
+            f(
+                &mut row_b[(depth * (to_x + x_skip) as usize)..(depth * (to_x + x_count) as usize)],
+                &row_a[(depth * (from.x + x_skip) as usize)..(depth * (from.x + x_count) as usize)],
+            );
+        }
+    }
+
+    // The same compositing as bitblt, but stretched, flipped and rotated.
+    // Instead of walking the source we walk every destination pixel the
+    // transformed rect could touch and look back at the nearest source pixel,
+    // so there are no holes and pixel art stays crisp.
+    pub fn bitblt_transformed(&mut self, src: &Texture, from: Rect, to: Vec2f, t: Transform) {
+        let (w, h) = t.size;
+        if t.angle == 0.0 && (w, h) == (from.w, from.h) && !t.flip_x && !t.flip_y {
+            self.bitblt(src, from, to);
+            return;
+        }
+        if w == 0 || h == 0 || from.w == 0 || from.h == 0 {
+            return;
+        }
+        assert!(src.valid_frame(from));
+        let depth = self.depth;
+        assert_eq!(depth, src.depth());
+        // Pivot in screen coordinates
+        let px = to.0 - self.position.0 + t.pivot.0;
+        let py = to.1 - self.position.1 + t.pivot.1;
+        let (sin, cos) = t.angle.sin_cos();
+        // Rotate the corners to find the screen-space bounding box
+        let corners = [
+            (0.0, 0.0),
+            (w as f32, 0.0),
+            (0.0, h as f32),
+            (w as f32, h as f32),
+        ];
+        let (mut x0, mut y0, mut x1, mut y1) = (f32::MAX, f32::MAX, f32::MIN, f32::MIN);
+        for (cx, cy) in corners.iter() {
+            let dx = cx - t.pivot.0;
+            let dy = cy - t.pivot.1;
+            let rx = px + dx * cos - dy * sin;
+            let ry = py + dx * sin + dy * cos;
+            x0 = x0.min(rx);
+            y0 = y0.min(ry);
+            x1 = x1.max(rx);
+            y1 = y1.max(ry);
+        }
+        let (cx0, cy0, cx1, cy1) = self.clip_edges();
+        let x0 = (x0.floor() as i32).max(cx0).min(cx1) as usize;
+        let x1 = (x1.ceil() as i32).max(cx0).min(cx1) as usize;
+        let y0 = (y0.floor() as i32).max(cy0).min(cy1) as usize;
+        let y1 = (y1.ceil() as i32).max(cy0).min(cy1) as usize;
+        self.touch(x0 as i32, y0 as i32, x1 as i32, y1 as i32);
+        let sx = from.w as f32 / w as f32;
+        let sy = from.h as f32 / h as f32;
+        let src_pitch = src.pitch();
+        let src_buf = src.buffer();
+        let dst_pitch = self.width * depth;
+        for y in y0..y1 {
+            let row = &mut self.framebuffer[(y * dst_pitch)..((y + 1) * dst_pitch)];
+            for x in x0..x1 {
+                // Sample at pixel centers, undoing the rotation around the pivot
+                let dx = x as f32 + 0.5 - px;
+                let dy = y as f32 + 0.5 - py;
+                let lx = dx * cos + dy * sin + t.pivot.0;
+                let ly = -dx * sin + dy * cos + t.pivot.1;
+                if lx < 0.0 || ly < 0.0 || lx >= w as f32 || ly >= h as f32 {
+                    continue;
+                }
+                let mut u = ((lx * sx) as i32).min(from.w as i32 - 1);
+                let mut v = ((ly * sy) as i32).min(from.h as i32 - 1);
+                if t.flip_x {
+                    u = from.w as i32 - 1 - u;
                 }
-                to[3] = ((fa + ta * (1.0 - fa)) * 255.0).round() as u8;
+                if t.flip_y {
+                    v = from.h as i32 - 1 - v;
+                }
+                let sidx = (from.y + v) as usize * src_pitch + (from.x + u) as usize * depth;
+                composite(
+                    &mut row[(x * depth)..((x + 1) * depth)],
+                    &src_buf[sidx..(sidx + depth)],
+                );
             }
         }
     }
 }
+
+// The overlap of two rects, or an empty rect inside a if they don't overlap
+fn intersect(a: Rect, b: Rect) -> Rect {
+    let x0 = a.x.max(b.x);
+    let y0 = a.y.max(b.y);
+    let x1 = (a.x + a.w as i32).min(b.x + b.w as i32);
+    let y1 = (a.y + a.h as i32).min(b.y + b.h as i32);
+    if x1 <= x0 || y1 <= y0 {
+        return Rect {
+            x: a.x,
+            y: a.y,
+            w: 0,
+            h: 0,
+        };
+    }
+    Rect {
+        x: x0,
+        y: y0,
+        w: (x1 - x0) as u16,
+        h: (y1 - y0) as u16,
+    }
+}
+
+// Where the row at height y crosses an ellipse
+fn ellipse_span(Vec2f(cx, cy): Vec2f, Vec2f(rx, ry): Vec2f, y: f32) -> Option<(f32, f32)> {
+    if rx <= 0.0 || ry <= 0.0 {
+        return None;
+    }
+    let dy = (y - cy) / ry;
+    if dy.abs() >= 1.0 {
+        return None;
+    }
+    let half = rx * (1.0 - dy * dy).sqrt();
+    Some((cx - half, cx + half))
+}
+
+// Where the row at height y crosses a convex polygon: between the leftmost
+// and rightmost edges it meets
+fn polygon_span(pts: &[Vec2f], y: f32) -> Option<(f32, f32)> {
+    let mut span: Option<(f32, f32)> = None;
+    for (i, &Vec2f(x0, y0)) in pts.iter().enumerate() {
+        let Vec2f(x1, y1) = pts[(i + 1) % pts.len()];
+        if y0 == y1 || y < y0.min(y1) || y > y0.max(y1) {
+            continue;
+        }
+        let x = x0 + (x1 - x0) * (y - y0) / (y1 - y0);
+        span = Some(match span {
+            Some((lo, hi)) => (lo.min(x), hi.max(x)),
+            None => (x, x),
+        });
+    }
+    span
+}
+
+// Where the row at height y crosses a rect with rounded corners
+fn rounded_span(x: f32, y: f32, w: f32, h: f32, radius: f32, row: f32) -> Option<(f32, f32)> {
+    if w <= 0.0 || h <= 0.0 || row < y || row >= y + h {
+        return None;
+    }
+    let r = radius.min(w / 2.0).min(h / 2.0).max(0.0);
+    // How far into a corner's curve the row is
+    let dy = if row < y + r {
+        y + r - row
+    } else if row > y + h - r {
+        row - (y + h - r)
+    } else {
+        0.0
+    };
+    let inset = r - (r * r - dy * dy).max(0.0).sqrt();
+    Some((x + inset, x + w - inset))
+}
+
+// The color at offset t between premultiplied stops
+fn gradient_at(stops: &[(f32, [f32; 4])], t: f32) -> [u8; 4] {
+    let i = stops.iter().position(|s| t < s.0).unwrap_or(stops.len());
+    let c = if i == 0 {
+        stops[0].1
+    } else if i == stops.len() {
+        stops[i - 1].1
+    } else {
+        let (t0, a) = stops[i - 1];
+        let (t1, b) = stops[i];
+        let k = (t - t0) / (t1 - t0);
+        [
+            a[0] + (b[0] - a[0]) * k,
+            a[1] + (b[1] - a[1]) * k,
+            a[2] + (b[2] - a[2]) * k,
+            a[3] + (b[3] - a[3]) * k,
+        ]
+    };
+    [
+        c[0].round() as u8,
+        c[1].round() as u8,
+        c[2].round() as u8,
+        c[3].round() as u8,
+    ]
+}
+
+// a * b / 255, rounded, without a division
+#[inline(always)]
+fn mul_255(a: u8, b: u8) -> u8 {
+    let t = a as u16 * b as u16 + 128;
+    ((t + (t >> 8)) >> 8) as u8
+}
+
+// Composite over, assume premultiplied rgba8888:
+// to = from + to * (1 - from.alpha), on every channel including alpha
+#[inline(always)]
+fn composite(to: &mut [u8], from: &[u8]) {
+    let inv = 255 - from[3];
+    for i in 0..4 {
+        to[i] = from[i].saturating_add(mul_255(to[i], inv));
+    }
+}
+
+// Composite a whole span of pixels.  This is the hot path for tilemaps, so
+// fully opaque spans (most tiles) are copied straight through, and the rest
+// is done four pixels at a time in plain integer math the compiler can vectorize.
+#[inline(always)]
+fn composite_row(to: &mut [u8], from: &[u8]) {
+    if from.iter().skip(3).step_by(4).all(|&a| a == 255) {
+        to.copy_from_slice(from);
+        return;
+    }
+    let mut to_chunks = to.chunks_exact_mut(16);
+    let mut from_chunks = from.chunks_exact(16);
+    for (to, from) in (&mut to_chunks).zip(&mut from_chunks) {
+        let mut out = [0_u8; 16];
+        for i in 0..16 {
+            let inv = 255 - from[(i & !3) + 3] as u16;
+            let t = to[i] as u16 * inv + 128;
+            let t = ((t + (t >> 8)) >> 8) + from[i] as u16;
+            out[i] = t.min(255) as u8;
+        }
+        to.copy_from_slice(&out);
+    }
+    for (to, from) in to_chunks
+        .into_remainder()
+        .chunks_exact_mut(4)
+        .zip(from_chunks.remainder().chunks_exact(4))
+    {
+        composite(to, from);
+    }
+}
+
+// Per-channel blend of premultiplied src over dst, both 0..=1.
+// Alpha is always combined like "over".
+#[inline(always)]
+fn composite_mode(to: &mut [u8], from: &[u8], mode: BlendMode) {
+    let ta = to[3] as f32 / 255.0;
+    let fa = from[3] as f32 / 255.0;
+    for i in 0..3 {
+        let t = to[i] as f32 / 255.0;
+        let f = from[i] as f32 / 255.0;
+        let c = match mode {
+            BlendMode::Over => f + t * (1.0 - fa),
+            BlendMode::Add => f + t,
+            BlendMode::Multiply => f * t + f * (1.0 - ta) + t * (1.0 - fa),
+            BlendMode::Screen => f + t - f * t,
+        };
+        to[i] = (c.min(1.0) * 255.0).round() as u8;
+    }
+    to[3] = ((fa + ta * (1.0 - fa)) * 255.0).round() as u8;
+}
+
+// Scale a premultiplied pixel's channels by per-channel factors
+#[inline(always)]
+fn modulate(px: &[u8], m: [f32; 4]) -> [u8; 4] {
+    [
+        (px[0] as f32 * m[0]).round() as u8,
+        (px[1] as f32 * m[1]).round() as u8,
+        (px[2] as f32 * m[2]).round() as u8,
+        (px[3] as f32 * m[3]).round() as u8,
+    ]
+}
+
+// Colors handed to the drawing calls are straight alpha, but composite
+// wants them premultiplied like our textures.
+#[inline(always)]
+pub(crate) fn premultiply(col: Rgba) -> [u8; 4] {
+    let a = col.3 as f32 / 255.0;
+    [
+        (col.0 as f32 * a).round() as u8,
+        (col.1 as f32 * a).round() as u8,
+        (col.2 as f32 * a).round() as u8,
+        col.3,
+    ]
+}
@@ -0,0 +1,264 @@
+use crate::animation::Animation;
+use crate::atlas::Region;
+use crate::error::{Error, Result};
+use crate::types::Rectf;
+use serde::de::{Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+// TexturePacker doesn't save frame times, so its frames get this many ticks
+// (a tenth of a second at 60fps)
+pub const DEFAULT_FRAME_TICKS: usize = 6;
+
+// The frames and animations from a sprite sheet exported by Aseprite or
+// TexturePacker (JSON hash or array).  Each animation is named after its
+// Aseprite tag, or its TexturePacker animation; without either, frames are
+// grouped by name, so walk_01.png, walk_02.png... make "walk".
+pub struct SpriteSheet {
+    // The sheet's image, next to the JSON
+    pub image: PathBuf,
+    pub frames: Vec<Rectf>,
+    pub animations: BTreeMap<String, Animation>,
+}
+
+#[derive(Deserialize)]
+struct SheetJson {
+    frames: Frames,
+    #[serde(default)]
+    meta: Meta,
+    // Only in TexturePacker's pixi.js flavor: frame names for each animation
+    #[serde(default)]
+    animations: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Deserialize, Default)]
+struct Meta {
+    image: Option<String>,
+    #[serde(default, rename = "frameTags")]
+    frame_tags: Vec<Tag>,
+}
+
+#[derive(Deserialize)]
+struct Tag {
+    name: String,
+    from: usize,
+    to: usize,
+    #[serde(default)]
+    direction: Option<String>,
+    // How many times to play; newer Aseprites write it as a string
+    #[serde(default)]
+    repeat: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct FrameData {
+    frame: Region,
+    #[serde(default)]
+    rotated: bool,
+    // In milliseconds
+    duration: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct NamedFrame {
+    filename: String,
+    #[serde(flatten)]
+    data: FrameData,
+}
+
+// Frames come either as a list with a filename in each, or as an object
+// keyed by filename.  Tags count frames by position, so the object is read
+// in file order rather than into a map that would sort it.
+struct Frames(Vec<(String, FrameData)>);
+
+impl<'de> Deserialize<'de> for Frames {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> std::result::Result<Self, D::Error> {
+        d.deserialize_any(FramesVisitor)
+    }
+}
+
+struct FramesVisitor;
+
+impl<'de> Visitor<'de> for FramesVisitor {
+    type Value = Frames;
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a list or an object of frames")
+    }
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Frames, A::Error> {
+        let mut frames = vec![];
+        while let Some(f) = seq.next_element::<NamedFrame>()? {
+            frames.push((f.filename, f.data));
+        }
+        Ok(Frames(frames))
+    }
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> std::result::Result<Frames, A::Error> {
+        let mut frames = vec![];
+        while let Some((name, data)) = map.next_entry::<String, FrameData>()? {
+            frames.push((name, data));
+        }
+        Ok(Frames(frames))
+    }
+}
+
+impl SpriteSheet {
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path).map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let json: SheetJson =
+            serde_json::from_reader(BufReader::new(file)).map_err(|source| Error::Json {
+                path: path.to_path_buf(),
+                source,
+            })?;
+        let bad = |why: String| Error::Sheet {
+            path: path.to_path_buf(),
+            why,
+        };
+
+        let mut frames = vec![];
+        let mut ticks = vec![];
+        for (name, data) in json.frames.0.iter() {
+            // Trimmed frames are fine, though their offsets are dropped;
+            // rotated ones can't be drawn as they are
+            if data.rotated {
+                return Err(bad(format!("{} is rotated; export without rotation", name)));
+            }
+            let r = &data.frame;
+            frames.push(Rectf {
+                x: r.x as f32,
+                y: r.y as f32,
+                w: r.w,
+                h: r.h,
+            });
+            ticks.push(data.duration.map_or(DEFAULT_FRAME_TICKS, ms_to_ticks));
+        }
+        let names: Vec<&str> = json.frames.0.iter().map(|(n, _)| n.as_str()).collect();
+
+        // Each animation as frame indices, and whether it loops
+        let mut sequences: BTreeMap<String, (Vec<usize>, bool)> = BTreeMap::new();
+        for tag in json.meta.frame_tags.iter() {
+            if tag.from > tag.to || tag.to >= frames.len() {
+                return Err(bad(format!(
+                    "tag {} covers frames {} to {}, but there are {}",
+                    tag.name,
+                    tag.from,
+                    tag.to,
+                    frames.len()
+                )));
+            }
+            let forward: Vec<usize> = (tag.from..=tag.to).collect();
+            let backward: Vec<usize> = forward.iter().rev().copied().collect();
+            // Ping-ponging doesn't show the end frames twice in a row
+            let inner = |v: &[usize]| v[1..v.len().saturating_sub(1).max(1)].to_vec();
+            let once = match tag.direction.as_deref().unwrap_or("forward") {
+                "forward" => forward,
+                "reverse" => backward,
+                "pingpong" => [forward, inner(&backward)].concat(),
+                "pingpong_reverse" => [backward, inner(&forward)].concat(),
+                other => {
+                    return Err(bad(format!(
+                        "tag {} has direction {}, which isn't one we know",
+                        tag.name, other
+                    )))
+                }
+            };
+            // No count, or 0, means forever
+            let repeat = match &tag.repeat {
+                Some(serde_json::Value::Number(n)) => n.as_u64().unwrap_or(0),
+                Some(serde_json::Value::String(s)) => s.parse().unwrap_or(0),
+                _ => 0,
+            };
+            let sequence = if repeat == 0 {
+                (once, true)
+            } else {
+                (once.repeat(repeat as usize), false)
+            };
+            sequences.insert(tag.name.clone(), sequence);
+        }
+        for (name, frame_names) in json.animations.iter() {
+            let mut indices = vec![];
+            for frame_name in frame_names.iter() {
+                match names.iter().position(|n| n == frame_name) {
+                    Some(i) => indices.push(i),
+                    None => {
+                        return Err(bad(format!(
+                            "animation {} uses frame {}, which isn't there",
+                            name, frame_name
+                        )))
+                    }
+                }
+            }
+            if indices.is_empty() {
+                return Err(bad(format!("animation {} has no frames", name)));
+            }
+            sequences.insert(name.clone(), (indices, true));
+        }
+        if sequences.is_empty() {
+            // Frames with the same name apart from a number, in number order
+            let mut groups: BTreeMap<&str, Vec<(Option<u32>, usize)>> = BTreeMap::new();
+            for (i, name) in names.iter().enumerate() {
+                let (prefix, number) = split_name(name);
+                groups.entry(prefix).or_default().push((number, i));
+            }
+            for (prefix, mut group) in groups {
+                group.sort_by_key(|&(number, _)| number);
+                let indices = group.into_iter().map(|(_, i)| i).collect();
+                sequences.insert(prefix.to_string(), (indices, true));
+            }
+        }
+
+        let animations = sequences
+            .into_iter()
+            .map(|(name, (indices, looping))| {
+                let animation = Animation::new(
+                    indices.iter().map(|&i| frames[i]).collect(),
+                    indices.iter().map(|&i| ticks[i]).collect(),
+                    looping,
+                );
+                (name, animation)
+            })
+            .collect();
+        // Aseprite and TexturePacker both name the image, but in case not,
+        // it's probably the same name as the JSON
+        let image = match &json.meta.image {
+            Some(image) => path.with_file_name(image),
+            None => path.with_extension("png"),
+        };
+        Ok(Self {
+            image,
+            frames,
+            animations,
+        })
+    }
+
+    pub fn animation(&self, name: &str) -> Option<&Animation> {
+        self.animations.get(name)
+    }
+}
+
+// At 60 updates a second, and at least one tick so no frame is skipped
+fn ms_to_ticks(ms: f64) -> usize {
+    ((ms * 60.0 / 1000.0).round() as usize).max(1)
+}
+
+// "walk_03.png" is frame 3 of "walk", and Aseprite's "Astronaut 3.aseprite"
+// is frame 3 of "Astronaut"
+fn split_name(name: &str) -> (&str, Option<u32>) {
+    let stem = match name.rfind('.') {
+        Some(dot) if dot > 0 => &name[..dot],
+        _ => name,
+    };
+    let prefix = stem.trim_end_matches(|c: char| c.is_ascii_digit());
+    let number = stem[prefix.len()..].parse().ok();
+    let prefix = prefix.trim_end_matches([' ', '_', '-', '.']);
+    if prefix.is_empty() {
+        (stem, None)
+    } else {
+        (prefix, number)
+    }
+}
@@ -47,6 +47,18 @@ impl Sprite {
         self.animation_state.current_tick = current_tick;
     }
 
+    fn current_frame(&self) -> Rect {
+        let frame_f = self
+            .animation
+            .current_frame(self.animation_state.current_tick);
+        Rect {
+            x: frame_f.x as i32,
+            y: frame_f.y as i32,
+            w: frame_f.w,
+            h: frame_f.h,
+        }
+    }
+
     pub fn on_screen(
         &self,
         camera_position: Vec2f,
@@ -65,21 +77,29 @@ impl Sprite {
 
 pub trait DrawSpriteExt {
     fn draw_sprite(&mut self, s: &Sprite);
+    fn draw_sprite_transformed(&mut self, s: &Sprite, t: Transform);
+    fn draw_sprite_blend(&mut self, s: &Sprite, blend: Blend);
 }
 
-use crate::screen::Screen;
+use crate::screen::{Blend, Screen, Transform};
 impl<'fb> DrawSpriteExt for Screen<'fb> {
     fn draw_sprite(&mut self, s: &Sprite) {
         // This works because we're only using a public method of Screen here,
         // and the private fields of sprite are visible inside this module
-        let frame_f = s.animation.current_frame(s.animation_state.current_tick);
-        let frame = Rect {
-            x: frame_f.x as i32,
-            y: frame_f.y as i32,
-            w: frame_f.w,
-            h: frame_f.h,
-        };
+        let frame = s.current_frame();
         let position = Vec2f(s.rect.x, s.rect.y);
         self.bitblt(&s.image, frame, position);
     }
+    // e.g. flip_x to face left, or a bigger size to zoom in
+    fn draw_sprite_transformed(&mut self, s: &Sprite, t: Transform) {
+        let frame = s.current_frame();
+        let position = Vec2f(s.rect.x, s.rect.y);
+        self.bitblt_transformed(&s.image, frame, position, t);
+    }
+    // Hit flashes, ghosts, fade-outs...
+    fn draw_sprite_blend(&mut self, s: &Sprite, blend: Blend) {
+        let frame = s.current_frame();
+        let position = Vec2f(s.rect.x, s.rect.y);
+        self.bitblt_blend(&s.image, frame, position, blend);
+    }
 }
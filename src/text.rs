@@ -45,6 +45,7 @@ impl<'fb> DrawTextExt for Screen<'fb> {
     }
 }
 
+#[derive(Clone)]
 pub struct Text {
     pub font: Rc<Font>,
     pub message: String,
@@ -1,13 +1,25 @@
-use crate::types::Rect;
+use crate::error::{Error, Result};
+use crate::screen::Screen;
+use crate::types::{Rect, Vec2f};
 use image::{self, RgbaImage};
+use std::cell::{Cell, Ref, RefCell};
 use std::path::Path;
 
-#[derive(PartialEq, Debug)]
+#[derive(Clone, Debug)]
 pub struct Texture {
-    image: Vec<u8>,
+    // In a RefCell so `reload` can swap the pixels under a shared Rc
+    image: RefCell<Vec<u8>>,
     pub width: usize,
     pub height: usize,
     depth: usize,
+    reloads: Cell<usize>,
+}
+
+// Same size and pixels, however many times either was reloaded
+impl PartialEq for Texture {
+    fn eq(&self, other: &Self) -> bool {
+        self.size() == other.size() && self.depth == other.depth && self.image == other.image
+    }
 }
 
 enum AlphaChannel {
@@ -15,12 +27,12 @@ enum AlphaChannel {
     Last,
 }
 impl Texture {
-    pub fn with_file(path: &Path) -> Self {
-        Self::new(
-            image::open(path)
-                .expect("Couldn't load image: {}")
-                .into_rgba8(),
-        )
+    pub fn with_file(path: &Path) -> Result<Self> {
+        let image = image::open(path).map_err(|source| Error::Image {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Ok(Self::new(image.into_rgba8()))
     }
     pub fn new(image: RgbaImage) -> Self {
         let (width, height) = image.dimensions();
@@ -30,8 +42,58 @@ impl Texture {
             width: width as usize,
             height: height as usize,
             depth: 4,
-            image,
+            image: RefCell::new(image),
+            reloads: Cell::new(0),
+        }
+    }
+    // Fully transparent, ready to be drawn into with `screen`
+    pub fn blank(width: usize, height: usize) -> Self {
+        Self::from_premultiplied(width, height, vec![0; width * height * 4])
+    }
+    // For pixels that are premultiplied already, like a framebuffer's
+    pub(crate) fn from_premultiplied(width: usize, height: usize, image: Vec<u8>) -> Self {
+        assert_eq!(image.len(), width * height * 4);
+        Self {
+            width,
+            height,
+            depth: 4,
+            image: RefCell::new(image),
+            reloads: Cell::new(0),
+        }
+    }
+    // Loads path again into this texture, so everything sharing it sees the
+    // new pixels.  The size can't change, since tilesets and animation frames
+    // were laid out for the old one.
+    pub fn reload(&self, path: &Path) -> Result<()> {
+        let new = Self::with_file(path)?;
+        if new.size() != self.size() {
+            return Err(Error::Resized {
+                path: path.to_path_buf(),
+                was: self.size(),
+                now: new.size(),
+            });
         }
+        *self.image.borrow_mut() = new.image.into_inner();
+        self.reloads.set(self.reloads.get() + 1);
+        Ok(())
+    }
+    // Goes up by one on every `reload`, for anything that keeps a copy of
+    // the pixels and needs to know when to redo it
+    pub fn reloads(&self) -> usize {
+        self.reloads.get()
+    }
+    // Draw into the texture just like into the window; `position` is the
+    // world point that lands on its top left.  Drawing keeps the pixels
+    // premultiplied, so the texture works as a bitblt source right after.
+    // A texture that's already shared in an Rc needs Rc::get_mut first.
+    pub fn screen(&mut self, position: Vec2f) -> Screen<'_> {
+        Screen::wrap(
+            self.image.get_mut(),
+            self.width,
+            self.height,
+            self.depth,
+            position,
+        )
     }
     pub fn depth(&self) -> usize {
         self.depth
@@ -42,8 +104,29 @@ impl Texture {
     pub fn pitch(&self) -> usize {
         self.width * self.depth
     }
-    pub fn buffer(&self) -> &[u8] {
-        &self.image
+    pub fn buffer(&self) -> Ref<'_, [u8]> {
+        Ref::map(self.image.borrow(), |image| &image[..])
+    }
+    // Premultiplied, like buffer
+    pub(crate) fn buffer_mut(&mut self) -> &mut [u8] {
+        self.image.get_mut()
+    }
+    // Back to straight alpha, the way image files store it
+    pub fn to_image(&self) -> RgbaImage {
+        let mut image = self.buffer().to_vec();
+        unpremultiply(&mut image);
+        RgbaImage::from_raw(self.width as u32, self.height as u32, image)
+            .expect("Texture buffer has the wrong size")
+    }
+    // Loading the file back with `with_file` gives the same texture,
+    // apart from rounding in see-through pixels
+    pub fn save_png(&self, path: &Path) -> Result<()> {
+        self.to_image()
+            .save_with_format(path, image::ImageFormat::Png)
+            .map_err(|source| Error::Image {
+                path: path.to_path_buf(),
+                source,
+            })
     }
     pub fn valid_frame(&self, frame: Rect) -> bool {
         0 <= frame.x
@@ -80,3 +163,15 @@ fn premultiply(img: &mut [u8], depth: usize, alpha: AlphaChannel) {
         }
     }
 }
+
+pub(crate) fn unpremultiply(img: &mut [u8]) {
+    for px in img.chunks_exact_mut(4) {
+        let a = px[3] as f32 / 255.0;
+        if a == 0.0 {
+            continue;
+        }
+        for component in px[0..3].iter_mut() {
+            *component = (*component as f32 / a).round().min(255.0) as u8;
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use crate::error::{Error, Result};
+use crate::screen::{Blend, BlendMode};
 use crate::Rect;
 use crate::Screen;
 use crate::Texture;
@@ -36,12 +38,23 @@ impl std::ops::Index<TileID> for Tileset {
     }
 }
 impl Tileset {
-    /// Create a new tileset
-    pub fn new(tiles: Vec<Tile>, texture: &Rc<Texture>) -> Self {
-        Self {
+    /// Create a new tileset; the texture needs a TILE_SZ square for every tile
+    pub fn new(tiles: Vec<Tile>, texture: &Rc<Texture>) -> Result<Self> {
+        let (w, h) = texture.size();
+        let room = (w / TILE_SZ) * (h / TILE_SZ);
+        if tiles.len() > room {
+            return Err(Error::Tiles(format!(
+                "{} tiles but the {}x{} texture only has room for {}",
+                tiles.len(),
+                w,
+                h,
+                room
+            )));
+        }
+        Ok(Self {
             tiles,
             texture: Rc::clone(texture),
-        }
+        })
     }
     /// Get the frame rect for a tile ID
     fn get_rect(&self, id: TileID) -> Rect {
@@ -82,18 +95,29 @@ impl Tilemap {
         dims: (usize, usize),
         tileset: &Rc<Tileset>,
         map: Vec<usize>,
-    ) -> Self {
-        assert_eq!(dims.0 * dims.1, map.len(), "Tilemap is the wrong size!");
-        assert!(
-            map.iter().all(|tid| tileset.contains(TileID(*tid))),
-            "Tilemap refers to nonexistent tiles"
-        );
-        Self {
+    ) -> Result<Self> {
+        if dims.0 * dims.1 != map.len() {
+            return Err(Error::Tiles(format!(
+                "a {}x{} tilemap needs {} tiles but got {}",
+                dims.0,
+                dims.1,
+                dims.0 * dims.1,
+                map.len()
+            )));
+        }
+        if let Some(tid) = map.iter().find(|tid| !tileset.contains(TileID(**tid))) {
+            return Err(Error::Tiles(format!(
+                "tilemap refers to tile {} but the tileset only has {}",
+                tid,
+                tileset.tiles.len()
+            )));
+        }
+        Ok(Self {
             position,
             dims,
             tileset: Rc::clone(tileset),
             map: map.into_iter().map(TileID).collect(),
-        }
+        })
     }
 
     pub fn generate_rand_map_2(p: f32, dims: (usize, usize), t1: TileID, t2: TileID) -> Vec<usize> {
@@ -198,6 +222,16 @@ impl Tilemap {
         self.dims
     }
 
+    /// Where the tile at `index` is in the world
+    pub fn tile_rect(&self, index: usize) -> Rect {
+        Rect {
+            x: self.position.0 as i32 + ((index % self.dims.0) * TILE_SZ) as i32,
+            y: self.position.1 as i32 + ((index / self.dims.0) * TILE_SZ) as i32,
+            w: TILE_SZ as u16,
+            h: TILE_SZ as u16,
+        }
+    }
+
     pub fn tile_at(&self, posn: Vec2f) -> Option<Tile> {
         if (posn.0 >= self.position.0 && posn.0 < self.position.0 + (self.dims.0 * TILE_SZ) as f32)
             || (posn.1 >= self.position.1
@@ -214,6 +248,17 @@ impl Tilemap {
     }
 
     pub fn draw(&self, screen: &mut Screen) {
+        self.draw_blend(screen, Blend::new(BlendMode::Over));
+    }
+
+    // Draw every visible tile tinted, faded or blended
+    pub fn draw_blend(&self, screen: &mut Screen, blend: Blend) {
+        self.draw_matching(screen, blend, |_| true);
+    }
+
+    // Draw just the visible tiles `keep` says yes to, e.g. to draw some
+    // kinds of tile differently from the rest
+    pub fn draw_matching<F: Fn(TileID) -> bool>(&self, screen: &mut Screen, blend: Blend, keep: F) {
         let Rect {
             x: sx,
             y: sy,
@@ -248,9 +293,12 @@ impl Tilemap {
             let ypx = (y * TILE_SZ) as f32 + self.position.1;
             // Here we can iterate through the column index and the relevant slice of the row in parallel
             for (x, id) in (left..right).zip(row[left..right].iter()) {
+                if !keep(*id) {
+                    continue;
+                }
                 let xpx = (x * TILE_SZ) as f32 + self.position.0;
                 let frame = self.tileset.get_rect(*id);
-                screen.bitblt(&self.tileset.texture, frame, Vec2f(xpx, ypx));
+                screen.bitblt_blend(&self.tileset.texture, frame, Vec2f(xpx, ypx), blend);
             }
         }
     }
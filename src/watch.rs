@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+// How often to look at the disk; every frame would be a lot of stat calls
+// for something a person is editing by hand
+const POLL_EVERY: Duration = Duration::from_millis(500);
+
+// Notices files changing in some folders (and the folders under them), for
+// reloading assets while the game runs.  Just compares modification times,
+// so there's nothing platform-specific and no background thread.
+pub struct Watcher {
+    dirs: Vec<PathBuf>,
+    modified: HashMap<PathBuf, SystemTime>,
+    last_poll: Instant,
+}
+
+impl Watcher {
+    // Whatever's in the folders now counts as unchanged
+    pub fn new(dirs: &[&str]) -> Self {
+        let mut watcher = Self {
+            dirs: dirs.iter().map(PathBuf::from).collect(),
+            modified: HashMap::new(),
+            last_poll: Instant::now(),
+        };
+        watcher.scan();
+        watcher
+    }
+
+    // Files that are new or have changed since last time; usually none.
+    // Only looks at the disk every so often, so it's fine to call each frame.
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        if self.last_poll.elapsed() < POLL_EVERY {
+            return vec![];
+        }
+        self.last_poll = Instant::now();
+        self.scan()
+    }
+
+    fn scan(&mut self) -> Vec<PathBuf> {
+        let mut files = vec![];
+        for dir in self.dirs.iter() {
+            list_files(dir, &mut files);
+        }
+        let mut changed = vec![];
+        for (path, time) in files {
+            // Editors often write a file in a couple of steps, so a change
+            // might get reported twice; reloading twice is harmless
+            if self.modified.insert(path.clone(), time) != Some(time) {
+                changed.push(path);
+            }
+        }
+        changed.sort();
+        changed
+    }
+}
+
+// A folder that can't be read (or a file that vanished partway through)
+// is skipped; it'll be picked up on a later poll if it comes back
+fn list_files(dir: &Path, files: &mut Vec<(PathBuf, SystemTime)>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let meta = match entry.metadata() {
+            Ok(meta) => meta,
+            Err(_) => continue,
+        };
+        if meta.is_dir() {
+            list_files(&path, files);
+        } else if let Ok(time) = meta.modified() {
+            files.push((path, time));
+        }
+    }
+}
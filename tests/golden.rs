@@ -0,0 +1,74 @@
+use anim2d::animation::*;
+use anim2d::canvas::Canvas;
+use anim2d::sprite::*;
+use anim2d::texture::Texture;
+use anim2d::tiles::*;
+use anim2d::types::*;
+use std::path::Path;
+use std::rc::Rc;
+
+// Run with UPDATE_GOLDEN=1 to write the reference images again after a
+// change that's meant to alter what gets drawn
+fn check_golden(canvas: &Canvas, name: &str) {
+    let path = Path::new("tests/golden").join(name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        canvas.save_png(&path).unwrap();
+    }
+    let golden = Texture::with_file(&path).unwrap();
+    assert_eq!(golden.size(), canvas.size(), "{} is the wrong size", name);
+    // Everything's opaque, so the file's straight alpha is the same bytes
+    let differ = golden
+        .buffer()
+        .chunks_exact(4)
+        .zip(canvas.buffer().chunks_exact(4))
+        .filter(|(g, c)| g != c)
+        .count();
+    assert_eq!(differ, 0, "{} pixels differ from {}", differ, name);
+}
+
+#[test]
+fn tilemap_and_sprite() {
+    let tile = Tile {
+        solid: false,
+        explode: false,
+        destructible: true,
+    };
+    let tiles = Rc::new(Texture::with_file(Path::new("content/tiles_dig.png")).unwrap());
+    let tileset = Rc::new(Tileset::new(vec![tile; 6], &tiles).unwrap());
+    let map = Tilemap::new(
+        Vec2f(0.0, 0.0),
+        (4, 3),
+        &tileset,
+        vec![5, 0, 1, 5, 5, 2, 3, 5, 5, 4, 0, 5],
+    )
+    .unwrap();
+    let astronaut = Rc::new(Texture::with_file(Path::new("content/Astronaut-Sheet.png")).unwrap());
+    let walk = Rc::new(Animation::new(
+        Rectf::create_frames(3, 7, 32, 32),
+        vec![5; 7],
+        true,
+    ));
+    let sprite = Sprite::new(
+        &astronaut,
+        &walk,
+        AnimationState::new(),
+        Rectf {
+            x: 40.0,
+            y: 24.0,
+            w: 32,
+            h: 32,
+        },
+        0.0,
+        0.0,
+    );
+
+    let mut canvas = Canvas::new(128, 96);
+    {
+        // Scrolled a little, so the camera offset gets checked too
+        let mut screen = canvas.screen(Vec2f(8.0, 4.0));
+        screen.clear(Rgba(80, 80, 80, 255));
+        map.draw(&mut screen);
+        screen.draw_sprite(&sprite);
+    }
+    check_golden(&canvas, "tilemap_and_sprite.png");
+}